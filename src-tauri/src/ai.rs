@@ -1,102 +1,397 @@
-use tauri::{AppHandle, Manager, Runtime};
+use crate::ai_prompt::{ChatMessage, PromptTemplate, StopMatcher};
+use crate::ai_models::{LoadedModel, ModelRegistry};
+use crate::ai_params::{GenerationOptions, Usage};
+use crate::ai_retrieval::{Citation, RetrievalOptions};
+use crate::ai_worker::{QueueMetrics, WorkerError, WorkerPool, DEFAULT_TIMEOUT};
+use tauri::{AppHandle, Emitter, Runtime};
 use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
 
 // Structure to hold the model state so we don't reload it every time
 #[derive(Default)]
 pub struct AIState {
-    pub model: Mutex<Option<Arc<dyn llm::Model>>>,
+    pub models: ModelRegistry,
+    // Cancellation flags of in-flight streaming requests, keyed by request id
+    pub active_requests: DashMap<String, Arc<AtomicBool>>,
+    // Blocking inference runs here, never on the tokio runtime
+    pub workers: WorkerPool,
+}
+
+impl AIState {
+    fn register_request(&self, request_id: &str) -> Result<Arc<AtomicBool>, String> {
+        // Check and insert under one shard lock so two requests can't claim the same id
+        match self.active_requests.entry(request_id.to_string()) {
+            Entry::Occupied(_) => Err(format!("AI request '{}' is already running", request_id)),
+            Entry::Vacant(entry) => {
+                let flag = Arc::new(AtomicBool::new(false));
+                entry.insert(flag.clone());
+                Ok(flag)
+            }
+        }
+    }
+
+    pub fn cancel(&self, request_id: &str) -> Result<(), String> {
+        let flag = self
+            .active_requests
+            .get(request_id)
+            .ok_or_else(|| format!("No running AI request '{}'", request_id))?;
+        flag.store(true, Ordering::SeqCst);
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AiRequest {
     pub prompt: String,
     pub system_context: String,
+    // Registry model id; the configured default when omitted
+    #[serde(default)]
+    pub model: Option<String>,
+    // Only used by the streaming endpoint; generated when omitted
+    #[serde(default)]
+    pub request_id: Option<String>,
+    // Inject matching chunks of the local document index into the system context
+    #[serde(default)]
+    pub retrieval: Option<RetrievalOptions>,
+    // temperature, top_k, top_p, repeat_penalty, seed, max_tokens, stop
+    #[serde(flatten)]
+    pub options: GenerationOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AiResponse {
     pub response: String,
+    pub usage: Usage,
+    // Retrieved chunks the prompt included, numbered as the model cites them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
 }
 
-async fn get_or_load_model<R: Runtime>(app: &AppHandle<R>, state: &AIState) -> Result<Arc<dyn llm::Model>, String> {
-    let mut model_lock = state.model.lock().await;
-    
-    if let Some(model) = &*model_lock {
-        return Ok(model.clone());
-    }
+// Payload of the `ai-token` event
+#[derive(Clone, Serialize)]
+pub struct TokenPayload {
+    pub request_id: String,
+    pub token: String,
+    pub done: bool,
+    pub cancelled: bool,
+    pub error: Option<String>,
+    // Set on the final (`done`) event
+    pub usage: Option<Usage>,
+}
 
-    // Resolve model path
-    let model_path = app.path().resource_dir()
-        .map_err(|e| e.to_string())?
-        .join("models")
-        .join("qwen2.5-0.5b-instruct-q4_k_m.gguf");
+pub fn new_request_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
 
-    if !model_path.exists() {
-        return Err(format!("Model file not found at: {:?}. Please run download_model.ps1.", model_path));
-    }
+pub(crate) async fn get_or_load_model<R: Runtime>(app: &AppHandle<R>, state: &AIState, model_id: Option<&str>) -> Result<LoadedModel, String> {
+    let dirs = crate::ai_models::model_dirs(app)?;
+    state.models.get(&dirs, model_id).await
+}
 
-    let architecture = llm::ModelArchitecture::Llama;
-    let tokenizer_source = llm::TokenizerSource::Embedded;
-    
-    let model = llm::load_dynamic(
-        Some(architecture),
-        &model_path,
-        tokenizer_source,
-        Default::default(),
-        |_| {}
-    ).map_err(|e| format!("Failed to load model: {}", e))?;
-
-    let model_arc: Arc<dyn llm::Model> = Arc::from(model);
-    *model_lock = Some(model_arc.clone());
-    
-    Ok(model_arc)
+// Like `get_or_load_model`, but an unknown model is the caller's error and a missing file is a 404
+pub(crate) async fn load_requested_model<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &AIState,
+    model_id: Option<&str>,
+) -> Result<LoadedModel, WorkerError> {
+    let entry = state.models.config().find(model_id).cloned().map_err(WorkerError::Invalid)?;
+    let dirs = crate::ai_models::model_dirs(app).map_err(WorkerError::Failed)?;
+    if !crate::ai_models::locate(&dirs, &entry.path).exists() {
+        return Err(WorkerError::NotFound(format!(
+            "Model file for '{}' not found. Import it with import_model.",
+            entry.id
+        )));
+    }
+    state.models.get(&dirs, Some(&entry.id)).await.map_err(WorkerError::Failed)
 }
 
 #[tauri::command]
 pub async fn ask_local_ai<R: Runtime>(
     app: AppHandle<R>,
-    state: tauri::State<'_, AIState>,
+    state: tauri::State<'_, Arc<AIState>>,
     prompt: String,
-    system_context: String
-) -> Result<String, String> {
-    let model = get_or_load_model(&app, &*state).await?;
-    run_inference(model, prompt, system_context).await
-}
-
-pub async fn run_inference(model: Arc<dyn llm::Model>, prompt: String, system_context: String) -> Result<String, String> {
-    // Construct Prompt (ChatML format for Qwen)
-    let formatted_prompt = format!(
-        "<|im_start|>system\n{}<|im_end|>\n<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
-        system_context,
-        prompt
-    );
+    system_context: String,
+    model: Option<String>,
+    options: Option<GenerationOptions>,
+    retrieval: Option<RetrievalOptions>
+) -> Result<AiResponse, String> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let loaded = get_or_load_model(&app, &state, model.as_deref()).await?;
+    let (system_context, citations) = crate::ai_retrieval::retrieve(&app, &system_context, &prompt, retrieval.as_ref());
+    let mut response = run_inference(&state, loaded, ChatMessage::single_turn(&system_context, &prompt), options).await?;
+    response.citations = citations;
+    Ok(response)
+}
+
+/// Streams the answer as `ai-token` events tagged with `request_id` and returns the full response.
+/// Generation stops early when `cancel_ai_request` is called with the same id.
+#[tauri::command]
+pub async fn ask_local_ai_stream<R: Runtime>(
+    app: AppHandle<R>,
+    state: tauri::State<'_, Arc<AIState>>,
+    request_id: String,
+    prompt: String,
+    system_context: String,
+    model: Option<String>,
+    options: Option<GenerationOptions>,
+    retrieval: Option<RetrievalOptions>
+) -> Result<AiResponse, String> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let loaded = get_or_load_model(&app, &state, model.as_deref()).await?;
+    let (system_context, citations) = crate::ai_retrieval::retrieve(&app, &system_context, &prompt, retrieval.as_ref());
+    let messages = ChatMessage::single_turn(&system_context, &prompt);
+    let cancel = state.register_request(&request_id)?;
 
+    let h_tokens = app.clone();
+    let id_tokens = request_id.clone();
+    let cancel_flag = cancel.clone();
+    let result = state.workers.run(cancel.clone(), DEFAULT_TIMEOUT, move || {
+        infer_blocking(loaded.model.as_ref(), &loaded.entry.prompt_template, &messages, &options, &cancel_flag, |token| {
+            let _ = h_tokens.emit("ai-token", TokenPayload {
+                request_id: id_tokens.clone(),
+                token: token.to_string(),
+                done: false,
+                cancelled: false,
+                error: None,
+                usage: None,
+            });
+            true
+        })
+    })
+    .await
+    .map_err(String::from)
+    .and_then(|r| r)
+    .map(|r| AiResponse { citations, ..r });
+
+    state.active_requests.remove(&request_id);
+    let _ = app.emit("ai-token", TokenPayload {
+        request_id,
+        token: String::new(),
+        done: true,
+        cancelled: cancel.load(Ordering::SeqCst),
+        error: result.as_ref().err().cloned(),
+        usage: result.as_ref().ok().map(|r| r.usage.clone()),
+    });
+
+    result
+}
+
+#[tauri::command]
+pub fn cancel_ai_request(state: tauri::State<'_, Arc<AIState>>, request_id: String) -> Result<(), String> {
+    state.cancel(&request_id)
+}
+
+#[tauri::command]
+pub fn ai_queue_status(state: tauri::State<'_, Arc<AIState>>) -> QueueMetrics {
+    state.workers.metrics()
+}
+
+pub async fn run_inference(
+    state: &AIState,
+    loaded: LoadedModel,
+    messages: Vec<ChatMessage>,
+    options: GenerationOptions,
+) -> Result<AiResponse, WorkerError> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = cancel.clone();
+    state.workers.run(cancel, DEFAULT_TIMEOUT, move || {
+        infer_blocking(loaded.model.as_ref(), &loaded.entry.prompt_template, &messages, &options, &flag, |_| true)
+    })
+    .await?
+    .map_err(WorkerError::Failed)
+}
+
+/// Runs generation on the calling thread, calling `on_token` for every new piece of text.
+/// Stops at the template's end-of-turn marker, when `cancel` is set or `on_token` returns false
+/// (e.g. the receiver went away).
+pub fn infer_blocking(
+    model: &dyn llm::Model,
+    template: &PromptTemplate,
+    messages: &[ChatMessage],
+    options: &GenerationOptions,
+    cancel: &AtomicBool,
+    on_token: impl FnMut(&str) -> bool,
+) -> Result<AiResponse, String> {
+    let mut session = model.start_session(Default::default());
+    let mut session_text = String::new();
+    generate(model, &mut session, &mut session_text, &template.render(messages), template, options, cancel, on_token)
+}
+
+/// Plain text continuation of `prompt` without any chat template
+pub fn complete_blocking(
+    model: &dyn llm::Model,
+    prompt: &str,
+    options: &GenerationOptions,
+    cancel: &AtomicBool,
+    on_token: impl FnMut(&str) -> bool,
+) -> Result<AiResponse, String> {
     let mut session = model.start_session(Default::default());
+    let mut session_text = String::new();
+    generate(model, &mut session, &mut session_text, prompt, &PromptTemplate::Raw, options, cancel, on_token)
+}
+
+/// Inference session kept between turns of a conversation, with the text it has consumed so far
+pub struct CachedSession {
+    model: Weak<dyn llm::Model>,
+    session: llm::InferenceSession,
+    text: String,
+}
+
+/// Like `infer_blocking`, but continues `cached` when the new prompt extends the text it has
+/// already consumed, so only the latest turn is fed through the model. Returns the session to keep.
+pub fn infer_cached(
+    loaded: &LoadedModel,
+    messages: &[ChatMessage],
+    options: &GenerationOptions,
+    cached: Option<CachedSession>,
+    cancel: &AtomicBool,
+    on_token: impl FnMut(&str) -> bool,
+) -> (Result<AiResponse, String>, CachedSession) {
+    let template = &loaded.entry.prompt_template;
+    let prompt = template.render(messages);
+
+    let same_model = |c: &CachedSession| {
+        c.model
+            .upgrade()
+            .is_some_and(|m| std::ptr::addr_eq(Arc::as_ptr(&m), Arc::as_ptr(&loaded.model)))
+    };
+    let mut cached = match cached {
+        Some(c) if same_model(&c) && !c.text.is_empty() && prompt.starts_with(&c.text) => c,
+        _ => CachedSession {
+            model: Arc::downgrade(&loaded.model),
+            session: loaded.model.start_session(Default::default()),
+            text: String::new(),
+        },
+    };
+
+    let new_text = prompt[cached.text.len()..].to_string();
+    let result = generate(
+        loaded.model.as_ref(),
+        &mut cached.session,
+        &mut cached.text,
+        &new_text,
+        template,
+        options,
+        cancel,
+        on_token,
+    );
+    (result, cached)
+}
+
+/// Feeds `prompt` into `session` and generates the answer. `session_text` tracks everything the
+/// session has consumed, including generated tokens past the point where the stop marker matched.
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    model: &dyn llm::Model,
+    session: &mut llm::InferenceSession,
+    session_text: &mut String,
+    prompt: &str,
+    template: &PromptTemplate,
+    options: &GenerationOptions,
+    cancel: &AtomicBool,
+    on_token: impl FnMut(&str) -> bool,
+) -> Result<AiResponse, String> {
+    let result = generate_tokens(model, session, session_text, prompt, template, options, cancel, on_token);
+    let outcome = match &result {
+        Ok(_) if cancel.load(Ordering::SeqCst) => "cancelled",
+        Ok(_) => "completed",
+        Err(_) => "failed",
+    };
+    crate::metrics::record_inference(outcome, result.as_ref().ok().map(|r| &r.usage));
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_tokens(
+    model: &dyn llm::Model,
+    session: &mut llm::InferenceSession,
+    session_text: &mut String,
+    prompt: &str,
+    template: &PromptTemplate,
+    options: &GenerationOptions,
+    cancel: &AtomicBool,
+    mut on_token: impl FnMut(&str) -> bool,
+) -> Result<AiResponse, String> {
+    let sampler = llm::samplers::build_sampler(model.tokenizer().len(), &[], &options.sampler_args())
+        .map_err(|e| format!("Invalid sampling parameters: {}", e))?;
+    let parameters = llm::InferenceParameters { sampler };
+    let mut rng = match options.seed {
+        Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+        None => rand::rngs::StdRng::from_entropy(),
+    };
+
+    let mut stops = template.stop_sequences();
+    stops.extend(options.stop.iter().cloned());
+    let mut stop = StopMatcher::new(stops);
     let mut response_text = String::new();
+    session_text.push_str(prompt);
+
+    let started = Instant::now();
+    let mut first_token = None;
+    let mut generated_tokens = 0;
 
-    let _ = session.infer::<std::convert::Infallible>(
-        model.as_ref(),
-        &mut rand::thread_rng(),
+    let result = session.infer::<std::convert::Infallible>(
+        model,
+        &mut rng,
         &llm::InferenceRequest {
-            prompt: formatted_prompt.as_str().into(),
-            parameters: &Default::default(),
+            prompt: prompt.into(),
+            parameters: &parameters,
             play_back_previous_tokens: false,
-            maximum_token_count: Some(1024),
+            maximum_token_count: Some(options.max_tokens()),
         },
         &mut Default::default(),
         |t| {
-            if let llm::InferenceResponse::SnapshotToken(token) = t {
-                response_text.push_str(&token);
+            if let Some(token) = generated_token(&t) {
+                generated_tokens += 1;
+                first_token.get_or_insert_with(|| started.elapsed());
+                session_text.push_str(token);
+                let (text, stopped) = stop.push(token);
+                if !text.is_empty() {
+                    response_text.push_str(&text);
+                    if !on_token(&text) {
+                        return Ok(llm::InferenceFeedback::Halt);
+                    }
+                }
+                if stopped {
+                    return Ok(llm::InferenceFeedback::Halt);
+                }
+            }
+            if cancel.load(Ordering::SeqCst) {
+                return Ok(llm::InferenceFeedback::Halt);
             }
             Ok(llm::InferenceFeedback::Continue)
         }
     );
 
-    Ok(response_text)
+    // A full context still leaves a usable partial answer
+    let prompt_tokens = match result {
+        Ok(stats) => stats.prompt_tokens,
+        Err(e) if generated_tokens == 0 => return Err(format!("Inference failed: {}", e)),
+        Err(_) => 0,
+    };
+
+    // Held-back text that never completed a stop sequence
+    let rest = stop.finish();
+    if !rest.is_empty() {
+        on_token(&rest);
+        response_text.push_str(&rest);
+    }
+
+    Ok(AiResponse {
+        response: response_text,
+        usage: Usage::new(prompt_tokens, generated_tokens, first_token, started.elapsed()),
+        citations: Vec::new(),
+    })
 }
 
 // Axum handler for external apps (Dashboard/Tactical)
@@ -106,15 +401,156 @@ pub async fn ai_http_handler(
 ) -> Response {
     let (app, ai_state) = &*state;
     
-    match get_or_load_model(app, ai_state).await {
-        Ok(model) => {
-            match run_inference(model, payload.prompt, payload.system_context).await {
-                Ok(resp) => axum::Json(AiResponse { response: resp }).into_response(),
-                Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    if let Err(e) = payload.options.validate() {
+        return (axum::http::StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    match get_or_load_model(app, ai_state, payload.model.as_deref()).await {
+        Ok(loaded) => {
+            let (system_context, citations) =
+                crate::ai_retrieval::retrieve(app, &payload.system_context, &payload.prompt, payload.retrieval.as_ref());
+            let messages = ChatMessage::single_turn(&system_context, &payload.prompt);
+            match run_inference(ai_state, loaded, messages, payload.options).await {
+                Ok(resp) => axum::Json(AiResponse { citations, ..resp }).into_response(),
+                Err(e) => worker_error_response(e),
             }
         },
         Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
 }
 
+// Newly generated tokens arrive as `InferredToken`; `SnapshotToken` is replayed from a restored session
+fn generated_token(response: &llm::InferenceResponse) -> Option<&str> {
+    match response {
+        llm::InferenceResponse::SnapshotToken(token) | llm::InferenceResponse::InferredToken(token) => Some(token),
+        _ => None,
+    }
+}
+
+// Server-Sent Events variant of /ai/ask: `start` (request id), `token`..., then `done` (the JSON
+// response with usage) or `error`. Closing the connection cancels generation.
+pub async fn ai_stream_http_handler(
+    axum::extract::State(state): axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>,
+    axum::Json(payload): axum::Json<AiRequest>
+) -> Response {
+    let (app, ai_state) = &*state;
+
+    if let Err(e) = payload.options.validate() {
+        return (axum::http::StatusCode::BAD_REQUEST, e).into_response();
+    }
+    let loaded = match get_or_load_model(app, ai_state, payload.model.as_deref()).await {
+        Ok(loaded) => loaded,
+        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    let request_id = payload.request_id.clone().unwrap_or_else(new_request_id);
+    let cancel = match ai_state.register_request(&request_id) {
+        Ok(flag) => flag,
+        Err(e) => return (axum::http::StatusCode::CONFLICT, e).into_response(),
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(64);
+    let _ = tx.try_send(Event::default().event("start").data(&request_id));
+
+    let (system_context, citations) =
+        crate::ai_retrieval::retrieve(app, &payload.system_context, &payload.prompt, payload.retrieval.as_ref());
+    let token_tx = tx.clone();
+    let flag = cancel.clone();
+    let job = ai_state.workers.enqueue(cancel, move || {
+        let messages = ChatMessage::single_turn(&system_context, &payload.prompt);
+        infer_blocking(loaded.model.as_ref(), &loaded.entry.prompt_template, &messages, &payload.options, &flag, |token| {
+            token_tx.blocking_send(Event::default().event("token").data(token)).is_ok()
+        })
+    });
+    let job = match job {
+        Ok(job) => job,
+        Err(e) => {
+            ai_state.active_requests.remove(&request_id);
+            return worker_error_response(e);
+        }
+    };
+
+    let ai_state = ai_state.clone();
+    tokio::spawn(async move {
+        let result = job
+            .wait(DEFAULT_TIMEOUT)
+            .await
+            .map_err(String::from)
+            .and_then(|r| r)
+            .map(|r| AiResponse { citations, ..r });
+        ai_state.active_requests.remove(&request_id);
+        let last = match result {
+            Ok(resp) => Event::default().event("done").json_data(resp).unwrap_or_default(),
+            Err(e) => Event::default().event("error").data(e),
+        };
+        let _ = tx.send(last).await;
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok::<_, std::convert::Infallible>(event), rx))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// 503 + Retry-After when the queue is full, 504 on timeout, 4xx for the caller's own mistakes
+pub(crate) fn worker_error_response(e: WorkerError) -> Response {
+    match e {
+        WorkerError::Busy { retry_after_secs } => (
+            e.status(),
+            [(axum::http::header::RETRY_AFTER, retry_after_secs.to_string())],
+            e.to_string(),
+        ).into_response(),
+        _ => (e.status(), e.to_string()).into_response(),
+    }
+}
+
+pub async fn ai_status_http_handler(
+    axum::extract::State(state): axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>,
+) -> Response {
+    let (_, ai_state) = &*state;
+    axum::Json(ai_state.workers.metrics()).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelRequest {
+    pub request_id: String,
+}
+
+pub async fn ai_cancel_http_handler(
+    axum::extract::State(state): axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>,
+    axum::Json(payload): axum::Json<CancelRequest>
+) -> Response {
+    let (_, ai_state) = &*state;
+    match ai_state.cancel(&payload.request_id) {
+        Ok(()) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (axum::http::StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
 use axum::response::Response;
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inferred_tokens_are_streamed() {
+        let event = llm::InferenceResponse::InferredToken("Hello".to_string());
+        assert_eq!(generated_token(&event), Some("Hello"));
+
+        let event = llm::InferenceResponse::PromptToken("system".to_string());
+        assert_eq!(generated_token(&event), None);
+        assert_eq!(generated_token(&llm::InferenceResponse::EotToken), None);
+    }
+
+    #[test]
+    fn duplicate_request_id_keeps_first_flag() {
+        let state = AIState::default();
+        let flag = state.register_request("req-1").unwrap();
+        assert!(state.register_request("req-1").is_err());
+
+        state.cancel("req-1").unwrap();
+        assert!(flag.load(Ordering::SeqCst), "cancel still reaches the first request");
+    }
+}
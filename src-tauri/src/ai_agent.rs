@@ -0,0 +1,557 @@
+//! Tool-calling agent on top of the local model
+//! The model may answer directly or reply with `{"tool": "...", "arguments": {...}}` to look up
+//! launcher state through a fixed set of read-only tools. Each result is fed back as the next
+//! message until the model answers or the step limit is reached; every call is kept in the trace.
+
+use crate::ai::{AIState, AiResponse};
+use crate::ai_models::LoadedModel;
+use crate::ai_params::{GenerationOptions, Usage};
+use crate::ai_prompt::{ChatMessage, Role};
+use crate::ai_worker::WorkerError;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::{AppHandle, Manager, Runtime};
+
+pub const DEFAULT_MAX_STEPS: usize = 4;
+const MAX_STEPS_LIMIT: usize = 10;
+/// Longer tool results are cut so they don't crowd the question out of the context
+const MAX_TOOL_RESULT_CHARS: usize = 4000;
+/// Folder in the app data dir where optimizer runs are saved (by the launcher or an external backtester)
+pub const OPTIMIZER_RESULTS_DIR: &str = "optimizer_results";
+/// The launcher's own latest optimizer run; each run replaces the previous one
+pub const LAUNCHER_RUN_FILE: &str = "launcher_latest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tool {
+    GetLatestPulse,
+    ListRunningApps,
+    GetAppLogs,
+    GetOptimizerResults,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::GetLatestPulse, Tool::ListRunningApps, Tool::GetAppLogs, Tool::GetOptimizerResults];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::GetLatestPulse => "get_latest_pulse",
+            Tool::ListRunningApps => "list_running_apps",
+            Tool::GetAppLogs => "get_app_logs",
+            Tool::GetOptimizerResults => "get_optimizer_results",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Tool> {
+        Tool::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    /// Argument shape and purpose, as shown to the model
+    fn description(&self) -> &'static str {
+        match self {
+            Tool::GetLatestPulse => {
+                r#"{"account_id"?: string} - latest balance, equity, drawdown and feed health of each trading account"#
+            }
+            Tool::ListRunningApps => "{} - ecosystem apps the launcher is running, with their ports",
+            Tool::GetAppLogs => r#"{"app_id": string, "limit"?: number} - the most recent log lines of an app"#,
+            Tool::GetOptimizerResults => r#"{"limit"?: number} - best parameter sets of the latest optimizer run"#,
+        }
+    }
+}
+
+/// System prompt explaining the tool-call protocol, followed by the caller's own context
+pub fn system_prompt(system_context: &str) -> String {
+    let mut prompt = String::from(
+        "You are the assistant of the DAAVFX trading launcher and can look up live launcher state with tools.\n\
+         To call a tool, reply with only a JSON object such as {\"tool\": \"list_running_apps\", \"arguments\": {}}.\n\
+         The result comes back in the next message. Call one tool per reply and never invent results.\n\
+         When you can answer, reply with plain text and no JSON.\n\nTools:\n",
+    );
+    for tool in Tool::ALL {
+        prompt.push_str(&format!("- {} {}\n", tool.name(), tool.description()));
+    }
+    if !system_context.trim().is_empty() {
+        prompt.push('\n');
+        prompt.push_str(system_context.trim());
+    }
+    prompt
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentAction {
+    Call { tool: String, arguments: Value },
+    Answer(String),
+}
+
+/// The first JSON value starting at a `{` in `text`, ignoring code fences and trailing prose
+pub(crate) fn first_json_object(text: &str) -> Option<Value> {
+    let start = text.find('{')?;
+    serde_json::Deserializer::from_str(&text[start..])
+        .into_iter::<Value>()
+        .next()
+        .and_then(|v| v.ok())
+}
+
+/// Reads a model reply: a JSON object with a `tool` key is a call, anything else is the answer
+pub fn parse_action(reply: &str) -> AgentAction {
+    let reply = reply.trim();
+    match first_json_object(reply) {
+        Some(Value::Object(mut fields)) => {
+            if let Some(Value::String(tool)) = fields.remove("tool") {
+                let arguments = fields.remove("arguments").unwrap_or_else(|| json!({}));
+                return AgentAction::Call { tool, arguments };
+            }
+            if let Some(Value::String(answer)) = fields.remove("answer") {
+                return AgentAction::Answer(answer);
+            }
+            AgentAction::Answer(reply.to_string())
+        }
+        _ => AgentAction::Answer(reply.to_string()),
+    }
+}
+
+/// Serializes a tool outcome for the prompt, cut to `MAX_TOOL_RESULT_CHARS`
+fn tool_message(tool: &str, outcome: &Result<Value, String>) -> String {
+    let body = match outcome {
+        Ok(value) => serde_json::to_string(value).unwrap_or_default(),
+        Err(e) => json!({ "error": e }).to_string(),
+    };
+    let body = match body.char_indices().nth(MAX_TOOL_RESULT_CHARS) {
+        Some((cut, _)) => format!("{}... (truncated)", &body[..cut]),
+        None => body,
+    };
+    format!("Result of {}:\n{}", tool, body)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentStep {
+    pub tool: String,
+    pub arguments: Value,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Answered,
+    /// The model still wanted a tool after `max_steps` calls; `answer` is its last reply
+    MaxSteps,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentReply {
+    pub answer: String,
+    pub steps: Vec<AgentStep>,
+    pub stop_reason: StopReason,
+    /// Summed over every model call
+    pub usage: Usage,
+}
+
+fn add_usage(total: &mut Usage, step: &Usage) {
+    total.time_to_first_token_ms = total.time_to_first_token_ms.or(step.time_to_first_token_ms);
+    total.prompt_tokens += step.prompt_tokens;
+    total.generated_tokens += step.generated_tokens;
+    total.total_ms += step.total_ms;
+    total.tokens_per_sec = if total.total_ms > 0 {
+        total.generated_tokens as f64 * 1000.0 / total.total_ms as f64
+    } else {
+        0.0
+    };
+}
+
+/// Model and tools the agent loop drives
+pub(crate) trait AgentBackend {
+    async fn infer(&mut self, messages: &[ChatMessage]) -> Result<AiResponse, WorkerError>;
+    async fn call_tool(&mut self, tool: Tool, arguments: &Value) -> Result<Value, String>;
+}
+
+pub(crate) async fn run_agent<B: AgentBackend>(
+    backend: &mut B,
+    system_context: &str,
+    question: &str,
+    max_steps: usize,
+) -> Result<AgentReply, WorkerError> {
+    let mut messages = vec![
+        ChatMessage::new(Role::System, system_prompt(system_context)),
+        ChatMessage::new(Role::User, question),
+    ];
+    let mut steps = Vec::new();
+    let mut usage = Usage::default();
+
+    loop {
+        let out_of_steps = steps.len() >= max_steps;
+        if out_of_steps {
+            messages.push(ChatMessage::new(
+                Role::User,
+                "No more tool calls are allowed. Answer now using the results above.",
+            ));
+        }
+
+        let response = backend.infer(&messages).await?;
+        add_usage(&mut usage, &response.usage);
+        let reply = response.response.trim().to_string();
+
+        let (tool, arguments) = match parse_action(&reply) {
+            AgentAction::Answer(answer) => {
+                return Ok(AgentReply { answer, steps, stop_reason: StopReason::Answered, usage });
+            }
+            AgentAction::Call { .. } if out_of_steps => {
+                return Ok(AgentReply { answer: reply, steps, stop_reason: StopReason::MaxSteps, usage });
+            }
+            AgentAction::Call { tool, arguments } => (tool, arguments),
+        };
+
+        let started = Instant::now();
+        let outcome = match Tool::from_name(&tool) {
+            Some(known) => backend.call_tool(known, &arguments).await,
+            None => Err(format!("Unknown tool '{}'", tool)),
+        };
+        messages.push(ChatMessage::new(Role::Assistant, reply));
+        messages.push(ChatMessage::new(Role::User, tool_message(&tool, &outcome)));
+        steps.push(AgentStep {
+            tool,
+            arguments,
+            duration_ms: started.elapsed().as_millis() as u64,
+            result: outcome.as_ref().ok().cloned(),
+            error: outcome.err(),
+        });
+    }
+}
+
+// ============ Tools ============
+
+fn limit_arg(arguments: &Value, default: usize, max: usize) -> usize {
+    arguments
+        .get("limit")
+        .and_then(Value::as_u64)
+        .map(|l| (l as usize).clamp(1, max))
+        .unwrap_or(default)
+}
+
+/// Saves a finished optimizer run where `get_optimizer_results` picks it up
+pub fn save_optimizer_run(dir: &Path, run: &impl Serialize) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let path = dir.join(LAUNCHER_RUN_FILE);
+    let json = serde_json::to_string_pretty(run).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(path)
+}
+
+/// Newest exported optimizer run, best `limit` results by score
+fn latest_optimizer_results(dir: &Path, limit: usize) -> Result<Value, String> {
+    let newest = std::fs::read_dir(dir)
+        .map_err(|_| format!("No optimizer results have been exported to {:?}", dir))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+        .ok_or_else(|| format!("No optimizer results have been exported to {:?}", dir))?;
+
+    let content = std::fs::read_to_string(&newest).map_err(|e| format!("Failed to read {:?}: {}", newest, e))?;
+    let run: Value = serde_json::from_str(&content).map_err(|e| format!("Invalid optimizer results {:?}: {}", newest, e))?;
+    let mut results = run.get("results").and_then(Value::as_array).cloned().unwrap_or_default();
+    let score = |r: &Value| r.get("score").and_then(Value::as_f64).unwrap_or(f64::MIN);
+    results.sort_by(|a, b| score(b).total_cmp(&score(a)));
+    let total_results = results.len();
+    results.truncate(limit);
+
+    Ok(json!({
+        "file": newest.file_name().map(|n| n.to_string_lossy().to_string()),
+        "total_combinations": run.get("total_combinations"),
+        "total_results": total_results,
+        "results": results,
+    }))
+}
+
+async fn call_tool<R: Runtime>(app: &AppHandle<R>, tool: Tool, arguments: &Value) -> Result<Value, String> {
+    match tool {
+        Tool::GetLatestPulse => {
+            let monitor = app
+                .try_state::<crate::pulse::PulseMonitor>()
+                .ok_or("Pulse monitor is not running")?;
+            let account = arguments.get("account_id").and_then(Value::as_str);
+            let readings = monitor.readings();
+            let accounts: Vec<Value> = monitor
+                .statuses()
+                .into_iter()
+                .filter(|s| account.is_none_or(|id| s.account_id == id))
+                .map(|status| {
+                    let reading = readings.iter().find(|(id, _, _)| *id == status.account_id).and_then(|r| r.2);
+                    json!({
+                        "account_id": status.account_id,
+                        "state": status.state,
+                        "last_pulse_age_secs": status.last_pulse_age_secs,
+                        "balance": reading.map(|r| r.balance),
+                        "equity": reading.map(|r| r.equity),
+                        "drawdown": reading.map(|r| r.drawdown),
+                        "margin_level": reading.and_then(|r| r.margin_level),
+                        "last_error": status.last_error,
+                    })
+                })
+                .collect();
+            if let (Some(id), true) = (account, accounts.is_empty()) {
+                return Err(format!("Unknown pulse account: {}", id));
+            }
+            Ok(Value::Array(accounts))
+        }
+        Tool::ListRunningApps => {
+            let registry = app.state::<crate::ProcessRegistry>();
+            let apps: Vec<_> = crate::app_control::list_apps(&registry)
+                .await
+                .into_iter()
+                .filter(|a| a.running)
+                .collect();
+            serde_json::to_value(apps).map_err(|e| e.to_string())
+        }
+        Tool::GetAppLogs => {
+            let app_id = arguments
+                .get("app_id")
+                .and_then(Value::as_str)
+                .ok_or("get_app_logs needs an app_id")?;
+            let buffer = app.state::<crate::app_control::LogBuffer>();
+            let lines: Vec<Value> = buffer
+                .tail(app_id, limit_arg(arguments, 20, 100))
+                .into_iter()
+                .map(|l| json!({ "type": l.log_type, "message": l.message }))
+                .collect();
+            Ok(Value::Array(lines))
+        }
+        Tool::GetOptimizerResults => latest_optimizer_results(&optimizer_results_dir(app), limit_arg(arguments, 5, 20)),
+    }
+}
+
+/// Where optimizer runs are saved and read from
+pub fn optimizer_results_dir<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
+    app.path()
+        .app_data_dir()
+        .map(|d| d.join(OPTIMIZER_RESULTS_DIR))
+        .unwrap_or_else(|_| PathBuf::from(OPTIMIZER_RESULTS_DIR))
+}
+
+struct LauncherBackend<'a, R: Runtime> {
+    app: &'a AppHandle<R>,
+    ai_state: &'a AIState,
+    loaded: LoadedModel,
+    options: GenerationOptions,
+}
+
+impl<R: Runtime> AgentBackend for LauncherBackend<'_, R> {
+    async fn infer(&mut self, messages: &[ChatMessage]) -> Result<AiResponse, WorkerError> {
+        crate::ai::run_inference(self.ai_state, self.loaded.clone(), messages.to_vec(), self.options.clone()).await
+    }
+
+    async fn call_tool(&mut self, tool: Tool, arguments: &Value) -> Result<Value, String> {
+        call_tool(self.app, tool, arguments).await
+    }
+}
+
+/// Answers `question` with access to the launcher tools
+pub async fn ask<R: Runtime>(
+    app: &AppHandle<R>,
+    ai_state: &AIState,
+    question: &str,
+    system_context: &str,
+    model: Option<&str>,
+    max_steps: Option<usize>,
+    options: GenerationOptions,
+) -> Result<AgentReply, WorkerError> {
+    options.validate().map_err(WorkerError::Invalid)?;
+    let loaded = crate::ai::load_requested_model(app, ai_state, model).await?;
+    let mut backend = LauncherBackend { app, ai_state, loaded, options };
+    let max_steps = max_steps.unwrap_or(DEFAULT_MAX_STEPS).min(MAX_STEPS_LIMIT);
+    run_agent(&mut backend, system_context, question, max_steps).await
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub async fn ask_agent<R: Runtime>(
+    app: AppHandle<R>,
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    question: String,
+    system_context: Option<String>,
+    model: Option<String>,
+    max_steps: Option<usize>,
+    options: Option<GenerationOptions>,
+) -> Result<AgentReply, String> {
+    Ok(ask(
+        &app,
+        &ai_state,
+        &question,
+        system_context.as_deref().unwrap_or_default(),
+        model.as_deref(),
+        max_steps,
+        options.unwrap_or_default(),
+    )
+    .await?)
+}
+
+// ============ HTTP ============
+
+type ServerState = axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>;
+
+#[derive(Debug, Deserialize)]
+pub struct AgentRequest {
+    pub question: String,
+    #[serde(default)]
+    pub system_context: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    #[serde(flatten)]
+    pub options: GenerationOptions,
+}
+
+/// POST /ai/agent
+pub async fn agent_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::Json(payload): axum::Json<AgentRequest>,
+) -> Response {
+    let (app, ai_state) = &*state;
+    if let Err(e) = payload.options.validate() {
+        return (axum::http::StatusCode::BAD_REQUEST, e).into_response();
+    }
+    let result = ask(
+        app,
+        ai_state,
+        &payload.question,
+        &payload.system_context,
+        payload.model.as_deref(),
+        payload.max_steps,
+        payload.options,
+    )
+    .await;
+    match result {
+        Ok(reply) => axum::Json(reply).into_response(),
+        Err(e) => crate::ai::worker_error_response(e),
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(
+            parse_action(r#"{"tool": "get_app_logs", "arguments": {"app_id": "dashboard"}}"#),
+            AgentAction::Call { tool: "get_app_logs".to_string(), arguments: json!({ "app_id": "dashboard" }) }
+        );
+        assert_eq!(
+            parse_action("```json\n{\"tool\": \"list_running_apps\"}\n```"),
+            AgentAction::Call { tool: "list_running_apps".to_string(), arguments: json!({}) }
+        );
+        assert_eq!(parse_action(r#"{"answer": "All good"}"#), AgentAction::Answer("All good".to_string()));
+        assert_eq!(parse_action(" Equity is 10,250. "), AgentAction::Answer("Equity is 10,250.".to_string()));
+        assert_eq!(Tool::from_name("get_latest_pulse"), Some(Tool::GetLatestPulse));
+        assert_eq!(Tool::from_name("rm_rf"), None);
+    }
+
+    /// Replays canned model replies and records tool calls
+    struct Scripted {
+        replies: Vec<&'static str>,
+        prompts: Vec<Vec<ChatMessage>>,
+    }
+
+    impl AgentBackend for Scripted {
+        async fn infer(&mut self, messages: &[ChatMessage]) -> Result<AiResponse, WorkerError> {
+            self.prompts.push(messages.to_vec());
+            let usage = Usage { generated_tokens: 5, total_ms: 100, ..Default::default() };
+            Ok(AiResponse { response: self.replies.remove(0).to_string(), usage, citations: Vec::new() })
+        }
+
+        async fn call_tool(&mut self, tool: Tool, _arguments: &Value) -> Result<Value, String> {
+            match tool {
+                Tool::ListRunningApps => Ok(json!([{ "id": "dashboard" }])),
+                _ => Err("unavailable".to_string()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_loop_traces_calls_and_injects_results() {
+        let mut backend = Scripted {
+            replies: vec![
+                r#"{"tool": "list_running_apps", "arguments": {}}"#,
+                r#"{"tool": "delete_everything"}"#,
+                "The dashboard is running.",
+            ],
+            prompts: Vec::new(),
+        };
+        let reply = run_agent(&mut backend, "", "What is running?", 4).await.unwrap();
+
+        assert_eq!(reply.stop_reason, StopReason::Answered);
+        assert_eq!(reply.answer, "The dashboard is running.");
+        assert_eq!(reply.steps.len(), 2);
+        assert_eq!(reply.steps[0].result, Some(json!([{ "id": "dashboard" }])));
+        assert_eq!(reply.steps[1].error.as_deref(), Some("Unknown tool 'delete_everything'"));
+        assert_eq!(reply.usage.generated_tokens, 15);
+
+        let last_prompt = backend.prompts.last().unwrap();
+        assert_eq!(last_prompt.len(), 6);
+        assert!(last_prompt[3].content.contains(r#"[{"id":"dashboard"}]"#));
+    }
+
+    #[tokio::test]
+    async fn test_agent_loop_stops_at_max_steps() {
+        let call = r#"{"tool": "get_latest_pulse", "arguments": {}}"#;
+        let mut backend = Scripted { replies: vec![call, call, call], prompts: Vec::new() };
+        let reply = run_agent(&mut backend, "", "Equity?", 2).await.unwrap();
+        assert_eq!(reply.stop_reason, StopReason::MaxSteps);
+        assert_eq!(reply.steps.len(), 2);
+        assert!(backend.prompts[2].last().unwrap().content.starts_with("No more tool calls"));
+    }
+
+    #[test]
+    fn test_latest_optimizer_results() {
+        let dir = std::env::temp_dir().join("daavfx_agent_optimizer_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(latest_optimizer_results(&dir, 5).is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let run = json!({
+            "total_combinations": 3,
+            "results": [{ "score": 1.0 }, { "score": 3.0 }, { "score": 2.0 }],
+        });
+        std::fs::write(dir.join("run.json"), run.to_string()).unwrap();
+        let latest = latest_optimizer_results(&dir, 2).unwrap();
+        assert_eq!(latest["total_results"], 3);
+        assert_eq!(latest["results"], json!([{ "score": 3.0 }, { "score": 2.0 }]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_saved_optimizer_run_reaches_the_tool() {
+        let dir = std::env::temp_dir().join("daavfx_agent_saved_run_test");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Shaped like the `OptimizationOutput` that `start_monte_carlo_optimization` returns
+        let run = |best: f64| {
+            json!({
+                "results": [{ "params": { "lots": 0.02 }, "profit": 120.0, "score": best }, { "score": 0.5 }],
+                "total_combinations": 9,
+                "elapsed_ms": 40,
+            })
+        };
+        save_optimizer_run(&dir, &run(1.5)).unwrap();
+        save_optimizer_run(&dir, &run(2.5)).unwrap();
+
+        let latest = latest_optimizer_results(&dir, 5).unwrap();
+        assert_eq!(latest["file"], LAUNCHER_RUN_FILE);
+        assert_eq!(latest["total_combinations"], 9);
+        assert_eq!(latest["total_results"], 2);
+        assert_eq!(latest["results"][0]["score"], 2.5, "the latest run replaces the previous one");
+        assert_eq!(latest["results"][0]["params"]["lots"], 0.02);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,588 @@
+//! Multi-turn AI conversations
+//! Conversations keep their message history in `conversations.json`; each new turn is answered
+//! with the whole history, dropping the oldest turns when it no longer fits the context window.
+//! The model's inference session is cached per conversation so earlier turns aren't re-evaluated;
+//! the model registry bounds that cache and drops a model's sessions when it unloads.
+
+use crate::ai::AIState;
+use crate::ai_params::{GenerationOptions, Usage};
+use crate::ai_prompt::{ChatMessage, Role};
+use crate::ai_worker::{WorkerError, DEFAULT_TIMEOUT};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, Runtime};
+
+const TITLE_LEN: usize = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    /// Registry model id; the configured default when None
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub system_context: String,
+    pub messages: Vec<ChatMessage>,
+    pub created_at: i64, // Unix timestamp
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub model: Option<String>,
+    pub message_count: usize,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatReply {
+    pub conversation_id: String,
+    pub message: ChatMessage,
+    /// Oldest messages left out of the prompt to fit the context window
+    pub dropped_messages: usize,
+    pub usage: Usage,
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl Conversation {
+    fn system_messages(&self) -> Vec<ChatMessage> {
+        if self.system_context.is_empty() {
+            Vec::new()
+        } else {
+            vec![ChatMessage::new(Role::System, self.system_context.clone())]
+        }
+    }
+
+    /// Full history for answering `question`, before fitting it into the context window
+    fn history_with(&self, question: ChatMessage) -> Vec<ChatMessage> {
+        let mut history = self.system_messages();
+        history.extend(self.messages.iter().cloned());
+        history.push(question);
+        history
+    }
+}
+
+/// Drops the oldest turns until `count` of the window is within `budget`.
+/// System messages and the latest message are always kept; the window never starts with an
+/// assistant reply. Returns the window and the number of dropped messages.
+pub fn fit_context(
+    messages: &[ChatMessage],
+    budget: usize,
+    count: impl Fn(&[ChatMessage]) -> usize,
+) -> (Vec<ChatMessage>, usize) {
+    let system: Vec<ChatMessage> = messages.iter().filter(|m| m.role == Role::System).cloned().collect();
+    let mut turns: Vec<ChatMessage> = messages.iter().filter(|m| m.role != Role::System).cloned().collect();
+    let mut dropped = 0;
+
+    loop {
+        let window: Vec<ChatMessage> = system.iter().chain(turns.iter()).cloned().collect();
+        if turns.len() <= 1 || count(&window) <= budget {
+            return (window, dropped);
+        }
+        turns.remove(0);
+        dropped += 1;
+        while turns.len() > 1 && turns[0].role == Role::Assistant {
+            turns.remove(0);
+            dropped += 1;
+        }
+    }
+}
+
+/// Conversation history, persisted as `conversations.json` in the app data dir
+pub struct ConversationStore {
+    path: PathBuf,
+    conversations: Mutex<HashMap<String, Conversation>>,
+    /// Conversations currently generating a reply
+    busy: Mutex<HashSet<String>>,
+}
+
+impl ConversationStore {
+    pub fn load(path: PathBuf) -> Self {
+        let conversations: Vec<Conversation> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            conversations: Mutex::new(conversations.into_iter().map(|c| (c.id.clone(), c)).collect()),
+            busy: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn save(&self, conversations: &HashMap<String, Conversation>) {
+        if let Some(parent) = self.path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let list: Vec<&Conversation> = conversations.values().collect();
+        let result = serde_json::to_string_pretty(&list)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("[AI Chat] Failed to save conversations: {}", e);
+        }
+    }
+
+    pub fn create(&self, system_context: String, model: Option<String>) -> Conversation {
+        let mut conversations = self.conversations.lock().unwrap();
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let mut id = format!("{:x}", nanos);
+        while conversations.contains_key(&id) {
+            id.push('0');
+        }
+        let now = unix_now();
+        let conversation = Conversation {
+            id: id.clone(),
+            title: String::new(),
+            model,
+            system_context,
+            messages: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        conversations.insert(id, conversation.clone());
+        self.save(&conversations);
+        conversation
+    }
+
+    /// Newest first
+    pub fn list(&self) -> Vec<ConversationSummary> {
+        let conversations = self.conversations.lock().unwrap();
+        let mut list: Vec<ConversationSummary> = conversations
+            .values()
+            .map(|c| ConversationSummary {
+                id: c.id.clone(),
+                title: c.title.clone(),
+                model: c.model.clone(),
+                message_count: c.messages.len(),
+                updated_at: c.updated_at,
+            })
+            .collect();
+        list.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| b.id.cmp(&a.id)));
+        list
+    }
+
+    pub fn get(&self, id: &str) -> Option<Conversation> {
+        self.conversations.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        let mut conversations = self.conversations.lock().unwrap();
+        conversations
+            .remove(id)
+            .ok_or_else(|| format!("Conversation '{}' not found", id))?;
+        self.save(&conversations);
+        Ok(())
+    }
+
+    /// Keeps the first `keep_messages` messages, e.g. to regenerate or edit from an earlier turn
+    pub fn truncate(&self, id: &str, keep_messages: usize) -> Result<Conversation, String> {
+        if self.busy.lock().unwrap().contains(id) {
+            return Err("Conversation is generating a reply".to_string());
+        }
+        let mut conversations = self.conversations.lock().unwrap();
+        let conversation = conversations
+            .get_mut(id)
+            .ok_or_else(|| format!("Conversation '{}' not found", id))?;
+        conversation.messages.truncate(keep_messages);
+        conversation.updated_at = unix_now();
+        let conversation = conversation.clone();
+        self.save(&conversations);
+        Ok(conversation)
+    }
+
+    /// Marks the conversation busy and returns it; one reply is generated at a time
+    fn begin_turn(&self, id: &str) -> Result<Conversation, WorkerError> {
+        let conversation = self
+            .get(id)
+            .ok_or_else(|| WorkerError::NotFound(format!("Conversation '{}' not found", id)))?;
+        if !self.busy.lock().unwrap().insert(id.to_string()) {
+            return Err(WorkerError::Conflict("Conversation is already generating a reply".to_string()));
+        }
+        Ok(conversation)
+    }
+
+    /// Clears the busy mark and appends the finished exchange, if any
+    fn end_turn(&self, id: &str, exchange: Option<(ChatMessage, ChatMessage)>) {
+        self.busy.lock().unwrap().remove(id);
+        let Some((question, answer)) = exchange else {
+            return;
+        };
+        let mut conversations = self.conversations.lock().unwrap();
+        if let Some(conversation) = conversations.get_mut(id) {
+            if conversation.title.is_empty() {
+                conversation.title = question.content.chars().take(TITLE_LEN).collect();
+            }
+            conversation.messages.push(question);
+            conversation.messages.push(answer);
+            conversation.updated_at = unix_now();
+            self.save(&conversations);
+        }
+    }
+}
+
+// ============ Chat ============
+
+/// Answers `content` in conversation `id` and appends both messages to its history
+pub async fn send_message<R: Runtime>(
+    app: &AppHandle<R>,
+    ai_state: &AIState,
+    store: &ConversationStore,
+    id: &str,
+    content: String,
+    options: GenerationOptions,
+) -> Result<ChatReply, WorkerError> {
+    options.validate().map_err(WorkerError::Invalid)?;
+    let conversation = store.begin_turn(id)?;
+    let question = ChatMessage::new(Role::User, content);
+
+    let result = answer(app, ai_state, &conversation, question.clone(), options).await;
+    match result {
+        Ok((response, dropped_messages)) => {
+            // Stored exactly as generated: the next prompt has to extend the cached session's text
+            let message = ChatMessage::new(Role::Assistant, response.response.clone());
+            store.end_turn(id, Some((question, message)));
+            Ok(ChatReply {
+                conversation_id: id.to_string(),
+                message: ChatMessage::new(Role::Assistant, response.response.trim()),
+                dropped_messages,
+                usage: response.usage,
+            })
+        }
+        Err(e) => {
+            store.end_turn(id, None);
+            Err(e)
+        }
+    }
+}
+
+async fn answer<R: Runtime>(
+    app: &AppHandle<R>,
+    ai_state: &AIState,
+    conversation: &Conversation,
+    question: ChatMessage,
+    options: GenerationOptions,
+) -> Result<(crate::ai::AiResponse, usize), WorkerError> {
+    let loaded = crate::ai::load_requested_model(app, ai_state, conversation.model.as_deref()).await?;
+
+    let history = conversation.history_with(question);
+
+    let context_size = loaded.entry.context_size;
+    // Leave room for the answer
+    let budget = context_size - options.max_tokens().min(context_size / 2);
+    let (window, dropped) = {
+        let template = &loaded.entry.prompt_template;
+        let tokenizer = loaded.model.tokenizer();
+        fit_context(&history, budget, |messages| {
+            let text = template.render(messages);
+            tokenizer.tokenize(&text, false).map(|t| t.len()).unwrap_or(text.len() / 4)
+        })
+    };
+
+    // The session leaves the cache while it is in use on a worker
+    let cached = ai_state.models.take_session(&conversation.id);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = cancel.clone();
+    let job_model = loaded.clone();
+    let (result, session) = ai_state
+        .workers
+        .run(cancel, DEFAULT_TIMEOUT, move || {
+            crate::ai::infer_cached(&job_model, &window, &options, cached, &flag, |_| true)
+        })
+        .await?;
+    ai_state.models.keep_session(&conversation.id, &loaded, session).await;
+
+    result.map(|response| (response, dropped)).map_err(WorkerError::Failed)
+}
+
+/// Drops idle cached sessions once a minute so an idle launcher gives their memory back
+pub async fn run_session_sweeper(ai_state: Arc<AIState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        ai_state.models.expire_sessions();
+    }
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub fn create_conversation(
+    store: tauri::State<'_, ConversationStore>,
+    system_context: Option<String>,
+    model: Option<String>,
+) -> Conversation {
+    store.create(system_context.unwrap_or_default(), model)
+}
+
+#[tauri::command]
+pub fn list_conversations(store: tauri::State<'_, ConversationStore>) -> Vec<ConversationSummary> {
+    store.list()
+}
+
+#[tauri::command]
+pub fn get_conversation(store: tauri::State<'_, ConversationStore>, conversation_id: String) -> Result<Conversation, String> {
+    store
+        .get(&conversation_id)
+        .ok_or_else(|| format!("Conversation '{}' not found", conversation_id))
+}
+
+#[tauri::command]
+pub async fn send_chat_message<R: Runtime>(
+    app: AppHandle<R>,
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    store: tauri::State<'_, ConversationStore>,
+    conversation_id: String,
+    content: String,
+    options: Option<GenerationOptions>,
+) -> Result<ChatReply, String> {
+    Ok(send_message(&app, &ai_state, &store, &conversation_id, content, options.unwrap_or_default()).await?)
+}
+
+#[tauri::command]
+pub fn truncate_conversation(
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    store: tauri::State<'_, ConversationStore>,
+    conversation_id: String,
+    keep_messages: usize,
+) -> Result<Conversation, String> {
+    ai_state.models.drop_session(&conversation_id);
+    store.truncate(&conversation_id, keep_messages)
+}
+
+#[tauri::command]
+pub fn delete_conversation(
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    store: tauri::State<'_, ConversationStore>,
+    conversation_id: String,
+) -> Result<(), String> {
+    ai_state.models.drop_session(&conversation_id);
+    store.delete(&conversation_id)
+}
+
+// ============ HTTP ============
+
+type ServerState = axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateConversationRequest {
+    #[serde(default)]
+    pub system_context: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessageRequest {
+    pub content: String,
+    #[serde(flatten)]
+    pub options: GenerationOptions,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TruncateRequest {
+    pub keep_messages: usize,
+}
+
+fn not_ready() -> Response {
+    (axum::http::StatusCode::SERVICE_UNAVAILABLE, "Conversation store is not ready yet").into_response()
+}
+
+fn not_found(id: &str) -> Response {
+    (axum::http::StatusCode::NOT_FOUND, format!("Conversation '{}' not found", id)).into_response()
+}
+
+pub async fn create_conversation_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::Json(payload): axum::Json<CreateConversationRequest>,
+) -> Response {
+    let (app, _) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    axum::Json(store.create(payload.system_context, payload.model)).into_response()
+}
+
+pub async fn list_conversations_http_handler(axum::extract::State(state): ServerState) -> Response {
+    let (app, _) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    axum::Json(store.list()).into_response()
+}
+
+pub async fn get_conversation_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Response {
+    let (app, _) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    match store.get(&id) {
+        Some(conversation) => axum::Json(conversation).into_response(),
+        None => not_found(&id),
+    }
+}
+
+pub async fn delete_conversation_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Response {
+    let (app, ai_state) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    ai_state.models.drop_session(&id);
+    match store.delete(&id) {
+        Ok(()) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(_) => not_found(&id),
+    }
+}
+
+pub async fn truncate_conversation_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::Json(payload): axum::Json<TruncateRequest>,
+) -> Response {
+    let (app, ai_state) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    if store.get(&id).is_none() {
+        return not_found(&id);
+    }
+    ai_state.models.drop_session(&id);
+    match store.truncate(&id, payload.keep_messages) {
+        Ok(conversation) => axum::Json(conversation).into_response(),
+        Err(e) => (axum::http::StatusCode::CONFLICT, e).into_response(),
+    }
+}
+
+pub async fn chat_message_http_handler(
+    axum::extract::State(state): ServerState,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::Json(payload): axum::Json<ChatMessageRequest>,
+) -> Response {
+    let (app, ai_state) = &*state;
+    let Some(store) = app.try_state::<ConversationStore>() else {
+        return not_ready();
+    };
+    if store.get(&id).is_none() {
+        return not_found(&id);
+    }
+    if let Err(e) = payload.options.validate() {
+        return (axum::http::StatusCode::BAD_REQUEST, e).into_response();
+    }
+    match send_message(app, ai_state, &store, &id, payload.content, payload.options).await {
+        Ok(reply) => axum::Json(reply).into_response(),
+        Err(e) => crate::ai::worker_error_response(e),
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_prompt::PromptTemplate;
+
+    fn chars(messages: &[ChatMessage]) -> usize {
+        messages.iter().map(|m| m.content.len()).sum()
+    }
+
+    #[test]
+    fn test_fit_context_drops_oldest_turns() {
+        let messages = vec![
+            ChatMessage::new(Role::System, "sys"),
+            ChatMessage::new(Role::User, "aaaaaaaaaa"),
+            ChatMessage::new(Role::Assistant, "bbbbbbbbbb"),
+            ChatMessage::new(Role::User, "cccc"),
+            ChatMessage::new(Role::Assistant, "dddd"),
+            ChatMessage::new(Role::User, "ee"),
+        ];
+
+        let (window, dropped) = fit_context(&messages, 100, chars);
+        assert_eq!((window.len(), dropped), (6, 0));
+
+        // The first exchange goes as a pair, the system prompt stays
+        let (window, dropped) = fit_context(&messages, 15, chars);
+        assert_eq!(dropped, 2);
+        assert_eq!(window[0].role, Role::System);
+        assert_eq!(window[1].content, "cccc");
+
+        // Never drops the question itself
+        let (window, dropped) = fit_context(&messages, 1, chars);
+        assert_eq!(dropped, 4);
+        assert_eq!(window.last().unwrap().content, "ee");
+    }
+
+    #[test]
+    fn test_store_turns() {
+        let path = std::env::temp_dir().join("daavfx_conversations_test.json");
+        let _ = std::fs::remove_file(&path);
+        let store = ConversationStore::load(path.clone());
+
+        let conversation = store.create("You are a risk assistant.".to_string(), None);
+        store.begin_turn(&conversation.id).unwrap();
+        assert!(
+            matches!(store.begin_turn(&conversation.id), Err(WorkerError::Conflict(_))),
+            "one reply at a time"
+        );
+        assert!(matches!(store.begin_turn("missing"), Err(WorkerError::NotFound(_))));
+        assert!(store.truncate(&conversation.id, 0).is_err());
+        store.end_turn(
+            &conversation.id,
+            Some((ChatMessage::new(Role::User, "Drawdown?"), ChatMessage::new(Role::Assistant, "3.2%"))),
+        );
+
+        let reloaded = ConversationStore::load(path.clone());
+        let saved = reloaded.get(&conversation.id).unwrap();
+        assert_eq!(saved.title, "Drawdown?");
+        assert_eq!(saved.messages.len(), 2);
+        assert_eq!(reloaded.truncate(&conversation.id, 1).unwrap().messages.len(), 1);
+        reloaded.delete(&conversation.id).unwrap();
+        assert!(reloaded.list().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_next_turn_extends_cached_session() {
+        let path = std::env::temp_dir().join("daavfx_conversations_cache_test.json");
+        let _ = std::fs::remove_file(&path);
+        let store = ConversationStore::load(path.clone());
+        let template = PromptTemplate::ChatMl;
+
+        let conversation = store.create("You are a risk assistant.".to_string(), None);
+        let question = ChatMessage::new(Role::User, "Drawdown?");
+        let prompt = template.render(&conversation.history_with(question.clone()));
+
+        // The session consumed the prompt and the raw tokens up to the stop marker
+        let generated = "\nDrawdown is 3.2%.\n";
+        let session_text = format!("{}{}<|im_end|>", prompt, generated);
+        store.begin_turn(&conversation.id).unwrap();
+        store.end_turn(&conversation.id, Some((question, ChatMessage::new(Role::Assistant, generated))));
+
+        let conversation = store.get(&conversation.id).unwrap();
+        let next = template.render(&conversation.history_with(ChatMessage::new(Role::User, "And margin?")));
+        assert!(next.starts_with(&session_text), "cached session would be discarded");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,365 @@
+//! Crash diagnosis with the local model
+//! Pulls the error blocks (rustc `error[E…]`, `npm ERR!`, panics and their backtraces) out of an
+//! app's captured output and asks the model for the probable cause and a fix. File references are
+//! read from the output itself, so they stay accurate whatever the model makes of them.
+
+use crate::ai::AIState;
+use crate::ai_params::{GenerationOptions, Usage};
+use crate::ai_prompt::ChatMessage;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Captured lines examined per diagnosis
+const TAIL_LINES: usize = 500;
+/// Lines sent when no error block is recognized
+const FALLBACK_LINES: usize = 60;
+const MAX_BLOCKS: usize = 8;
+const MAX_BLOCK_LINES: usize = 40;
+const MAX_REFERENCES: usize = 10;
+/// Upper bound on the log excerpt in the prompt; the oldest blocks are dropped first
+const MAX_EXCERPT_CHARS: usize = 6000;
+
+const SYSTEM_PROMPT: &str = "You diagnose build and runtime failures of Rust (Tauri) and Node (Vite) apps.\n\
+Reply with only a JSON object: {\"cause\": string, \"fix\": string, \"references\": [{\"file\": string, \"line\": number}]}.\n\
+`cause` is the most probable root cause in one or two sentences, `fix` the concrete change to make.\n\
+Only reference files and lines that appear in the output. If the output is inconclusive, say so in `cause`.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Rustc,
+    Npm,
+    Panic,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorBlock {
+    pub kind: BlockKind,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SourceRef {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureDiagnosis {
+    pub app_id: String,
+    pub cause: String,
+    pub suggested_fix: Option<String>,
+    pub references: Vec<SourceRef>,
+    pub error_blocks: Vec<ErrorBlock>,
+    /// Captured lines that were examined
+    pub analyzed_lines: usize,
+    pub usage: Usage,
+}
+
+/// Removes ANSI color and cursor sequences (cargo and Vite color their output)
+pub fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            out.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn is_npm_error(line: &str) -> bool {
+    line.contains("npm ERR!") || line.trim_start().starts_with("npm error")
+}
+
+fn block_start(line: &str) -> Option<BlockKind> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("error[E")
+        || (trimmed.starts_with("error:")
+            && !trimmed.starts_with("error: could not compile")
+            && !trimmed.starts_with("error: aborting due to"))
+    {
+        Some(BlockKind::Rustc)
+    } else if is_npm_error(line) {
+        Some(BlockKind::Npm)
+    } else if line.contains("' panicked at ") {
+        Some(BlockKind::Panic)
+    } else {
+        None
+    }
+}
+
+/// Whether `line` belongs to the block of `kind` that has `len` lines so far
+fn continues(kind: BlockKind, line: &str, len: usize) -> bool {
+    if line.trim().is_empty() {
+        return false;
+    }
+    match kind {
+        BlockKind::Npm => is_npm_error(line),
+        BlockKind::Rustc => block_start(line).is_none(),
+        // The panic message sits unindented on the line after `panicked at file:line:col:`
+        BlockKind::Panic => {
+            len == 1
+                || line.starts_with(char::is_whitespace)
+                || line.starts_with("stack backtrace:")
+                || line.starts_with("note:")
+        }
+    }
+}
+
+/// Appends `block`, dropping an earlier identical one (dev servers repeat errors on every reload)
+fn finish(block: ErrorBlock, blocks: &mut Vec<ErrorBlock>) {
+    blocks.retain(|b| *b != block);
+    blocks.push(block);
+}
+
+/// Error blocks in `lines`, most recent last; repeats of the same block are kept once
+pub fn extract_error_blocks(lines: &[String]) -> Vec<ErrorBlock> {
+    let mut blocks: Vec<ErrorBlock> = Vec::new();
+    let mut current: Option<ErrorBlock> = None;
+
+    for line in lines {
+        if let Some(block) = current.as_mut() {
+            if continues(block.kind, line, block.lines.len()) {
+                if block.lines.len() < MAX_BLOCK_LINES {
+                    block.lines.push(line.clone());
+                }
+                continue;
+            }
+            finish(current.take().unwrap(), &mut blocks);
+        }
+        if let Some(kind) = block_start(line) {
+            current = Some(ErrorBlock { kind, lines: vec![line.clone()] });
+        }
+    }
+    if let Some(block) = current {
+        finish(block, &mut blocks);
+    }
+
+    let skip = blocks.len().saturating_sub(MAX_BLOCKS);
+    blocks.split_off(skip)
+}
+
+/// Parses `path:line[:column]`, as in `--> src/main.rs:10:5` or `at src/App.tsx:3:7`
+fn parse_location(token: &str) -> Option<SourceRef> {
+    let token = token.trim_matches(|c: char| matches!(c, '(' | ')' | ',' | '\'' | '"' | '`')).trim_end_matches(':');
+    let mut parts = token.rsplitn(3, ':');
+    let last: u32 = parts.next()?.parse().ok()?;
+    let middle = parts.next()?;
+    let (file, line, column) = match (middle.parse::<u32>(), parts.next()) {
+        (Ok(line), Some(file)) => (file, line, Some(last)),
+        _ => (token.rsplit_once(':')?.0, last, None),
+    };
+    // Drop URL schemes and frames inside the standard library or dependencies
+    let file = file.strip_prefix("file://").unwrap_or(file);
+    let looks_like_file = file.rsplit_once('.').is_some_and(|(_, ext)| {
+        ext.chars().all(|c| c.is_ascii_alphanumeric()) && ext.chars().any(|c| c.is_ascii_alphabetic())
+    });
+    let external = ["/rustc/", ".cargo", "node:internal", "http:", "https:"].iter().any(|p| file.contains(p));
+    if file.is_empty() || !looks_like_file || external || line == 0 {
+        return None;
+    }
+    Some(SourceRef { file: file.to_string(), line, column })
+}
+
+/// Every distinct source location mentioned in the blocks, in order of appearance
+pub fn source_refs(blocks: &[ErrorBlock]) -> Vec<SourceRef> {
+    let mut refs: Vec<SourceRef> = Vec::new();
+    for line in blocks.iter().flat_map(|b| &b.lines) {
+        for location in line.split_whitespace().filter_map(parse_location) {
+            if !refs.contains(&location) && refs.len() < MAX_REFERENCES {
+                refs.push(location);
+            }
+        }
+    }
+    refs
+}
+
+/// The log excerpt sent to the model: the error blocks, or the last lines when there are none
+pub fn build_excerpt(blocks: &[ErrorBlock], lines: &[String]) -> String {
+    if blocks.is_empty() {
+        let tail = &lines[lines.len().saturating_sub(FALLBACK_LINES)..];
+        return format!("No error blocks were recognized. Last output lines:\n{}", tail.join("\n"));
+    }
+    let mut sections: Vec<String> = Vec::new();
+    let mut total = 0;
+    for block in blocks.iter().rev() {
+        let text = block.lines.join("\n");
+        if total + text.len() > MAX_EXCERPT_CHARS && !sections.is_empty() {
+            break;
+        }
+        total += text.len();
+        sections.push(text);
+    }
+    sections.reverse();
+    sections.join("\n\n")
+}
+
+/// Reads the model's JSON reply; plain text is taken as the cause
+pub fn parse_diagnosis(reply: &str) -> (String, Option<String>, Vec<SourceRef>) {
+    let Some(Value::Object(fields)) = crate::ai_agent::first_json_object(reply) else {
+        return (reply.trim().to_string(), None, Vec::new());
+    };
+    let text = |key: &str| fields.get(key).and_then(Value::as_str).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let references = fields
+        .get("references")
+        .and_then(Value::as_array)
+        .map(|refs| {
+            refs.iter()
+                .filter_map(|r| {
+                    Some(SourceRef {
+                        file: r.get("file")?.as_str()?.to_string(),
+                        line: r.get("line")?.as_u64()? as u32,
+                        column: r.get("column").and_then(Value::as_u64).map(|c| c as u32),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    (text("cause").unwrap_or_else(|| reply.trim().to_string()), text("fix"), references)
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub async fn explain_app_failure<R: Runtime>(
+    app: AppHandle<R>,
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    app_id: String,
+    model: Option<String>,
+) -> Result<FailureDiagnosis, String> {
+    let buffer = app.state::<crate::app_control::LogBuffer>();
+    let lines: Vec<String> = buffer
+        .tail(&app_id, TAIL_LINES)
+        .into_iter()
+        .map(|l| strip_ansi(&l.message))
+        .collect();
+    if lines.is_empty() {
+        return Err(format!("No output has been captured for '{}'", app_id));
+    }
+
+    let error_blocks = extract_error_blocks(&lines);
+    let prompt = format!("Output of the app '{}':\n\n{}", app_id, build_excerpt(&error_blocks, &lines));
+    let options = GenerationOptions {
+        temperature: Some(0.2),
+        max_tokens: Some(512),
+        ..Default::default()
+    };
+    let loaded = crate::ai::get_or_load_model(&app, &ai_state, model.as_deref()).await?;
+    let response = crate::ai::run_inference(&ai_state, loaded, ChatMessage::single_turn(SYSTEM_PROMPT, &prompt), options).await?;
+
+    let (cause, suggested_fix, suggested_refs) = parse_diagnosis(&response.response);
+    let mut references = source_refs(&error_blocks);
+    // Model references are kept only when they point at a file the output mentions
+    for r in suggested_refs {
+        if !references.contains(&r) && lines.iter().any(|l| l.contains(&r.file)) && references.len() < MAX_REFERENCES {
+            references.push(r);
+        }
+    }
+
+    Ok(FailureDiagnosis {
+        app_id,
+        cause,
+        suggested_fix,
+        references,
+        error_blocks,
+        analyzed_lines: lines.len(),
+        usage: response.usage,
+    })
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_extract_rustc_and_npm_blocks() {
+        let output = lines(
+            "   Compiling launcher v0.1.0\n\
+             error[E0425]: cannot find value `x` in this scope\n\
+             \x20 --> src/main.rs:10:5\n\
+             \x20  |\n\
+             10 |     x + 1\n\
+             \x20  |     ^ not found in this scope\n\
+             \n\
+             error: could not compile `launcher` (bin \"launcher\") due to 1 previous error\n\
+             npm ERR! code ELIFECYCLE\n\
+             npm ERR! errno 1\n\
+             done",
+        );
+        let blocks = extract_error_blocks(&output);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].kind, BlockKind::Rustc);
+        assert_eq!(blocks[0].lines.len(), 5);
+        assert_eq!(blocks[1].kind, BlockKind::Npm);
+        assert_eq!(blocks[1].lines.len(), 2);
+        assert_eq!(
+            source_refs(&blocks),
+            vec![SourceRef { file: "src/main.rs".to_string(), line: 10, column: Some(5) }]
+        );
+    }
+
+    #[test]
+    fn test_extract_panic_with_backtrace() {
+        let output = lines(
+            "\x1b[32mready\x1b[0m\n\
+             thread 'main' panicked at src/pulse.rs:88:14:\n\
+             called `Option::unwrap()` on a `None` value\n\
+             stack backtrace:\n\
+             \x20  0: rust_begin_unwind\n\
+             \x20            at /rustc/abc/library/std/src/panicking.rs:652:5\n\
+             \x20  1: launcher::pulse::poll\n\
+             \x20            at ./src/pulse.rs:88:14\n\
+             next line",
+        );
+        let output: Vec<String> = output.iter().map(|l| strip_ansi(l)).collect();
+        assert_eq!(output[0], "ready");
+        let blocks = extract_error_blocks(&output);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].kind, BlockKind::Panic);
+        assert_eq!(blocks[0].lines.len(), 7);
+        let refs = source_refs(&blocks);
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].file, "src/pulse.rs");
+        assert_eq!(refs[1].file, "./src/pulse.rs");
+    }
+
+    #[test]
+    fn test_repeated_blocks_are_kept_once() {
+        let output = lines("error: failed to resolve import\n\nerror: failed to resolve import\n\nok");
+        assert_eq!(extract_error_blocks(&output).len(), 1);
+        assert!(build_excerpt(&[], &output).starts_with("No error blocks"));
+    }
+
+    #[test]
+    fn test_parse_diagnosis() {
+        let reply = r#"```json
+{"cause": "x is not defined", "fix": "Declare x", "references": [{"file": "src/main.rs", "line": 10}]}
+```"#;
+        let (cause, fix, refs) = parse_diagnosis(reply);
+        assert_eq!(cause, "x is not defined");
+        assert_eq!(fix.as_deref(), Some("Declare x"));
+        assert_eq!(refs[0].line, 10);
+
+        let (cause, fix, refs) = parse_diagnosis("The port is already in use.");
+        assert_eq!(cause, "The port is already in use.");
+        assert!(fix.is_none() && refs.is_empty());
+    }
+}
@@ -0,0 +1,943 @@
+//! Model files: import, integrity checks and GGUF header validation
+//! Models are imported into `<app data>/models` from a local path or an HTTP(S) mirror.
+//! Downloads go to `<file>.part` and resume from its length with a `Range` request. The file
+//! only replaces the model once its SHA-256 matches the registry's `sha256` and its header is valid.
+//! Progress is emitted as `model-import-progress` (topic `models`).
+
+use crate::ai::AIState;
+use crate::ai_models::ModelEntry;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::io::AsyncWriteExt;
+
+pub const CHECKS_FILE: &str = "model_checks.json";
+
+const BUFFER_SIZE: usize = 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A mirror that sends nothing for this long is treated as gone; the next import resumes
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 3;
+
+// ============ Header ============
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelHeader {
+    /// `gguf`, or a legacy GGML container (`ggml`, `ggmf`, `ggjt`, `ggla`) that carries no metadata
+    pub format: String,
+    pub version: Option<u32>,
+    pub architecture: Option<String>,
+    pub quantization: Option<String>,
+    /// Context length the model was trained with
+    pub context_length: Option<u64>,
+    /// Layers and embedding width, which size the KV cache of an inference session
+    pub block_count: Option<u64>,
+    pub embedding_length: Option<u64>,
+    pub tensor_count: Option<u64>,
+}
+
+const LEGACY_MAGICS: [(u32, &str); 4] = [
+    (0x6767_6d6c, "ggml"),
+    (0x6767_6d66, "ggmf"),
+    (0x6767_6a74, "ggjt"),
+    (0x6767_6c61, "ggla"),
+];
+
+/// `general.file_type` values of the common llama.cpp quantizations
+fn quantization_name(file_type: u64) -> String {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        _ => return format!("file type {}", file_type),
+    };
+    name.to_string()
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32, String> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf).map_err(|e| format!("Truncated model header: {}", e))?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64, String> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf).map_err(|e| format!("Truncated model header: {}", e))?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn skip(r: &mut impl Seek, bytes: u64) -> Result<(), String> {
+    let offset = i64::try_from(bytes).map_err(|_| "Corrupt model header".to_string())?;
+    r.seek(SeekFrom::Current(offset)).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Reads a GGUF string, or skips it and returns None when it is longer than `keep_up_to`
+fn read_string<Rd: Read + Seek>(r: &mut Rd, keep_up_to: u64) -> Result<Option<String>, String> {
+    let len = read_u64(r)?;
+    if len > keep_up_to {
+        skip(r, len)?;
+        return Ok(None);
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf).map_err(|e| format!("Truncated model header: {}", e))?;
+    Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+enum MetaValue {
+    Uint(u64),
+    Str(String),
+    Skipped,
+}
+
+/// Byte size of fixed-size GGUF value types
+fn scalar_size(value_type: u32) -> Option<u64> {
+    match value_type {
+        0 | 1 | 7 => Some(1),
+        2 | 3 => Some(2),
+        4..=6 => Some(4),
+        10..=12 => Some(8),
+        _ => None,
+    }
+}
+
+fn read_value<Rd: Read + Seek>(r: &mut Rd, value_type: u32, depth: usize) -> Result<MetaValue, String> {
+    match value_type {
+        4 => Ok(MetaValue::Uint(read_u32(r)? as u64)),
+        10 => Ok(MetaValue::Uint(read_u64(r)?)),
+        8 => Ok(read_string(r, 1024)?.map(MetaValue::Str).unwrap_or(MetaValue::Skipped)),
+        // Arrays (the tokenizer vocabulary among them) are skipped without being read
+        9 if depth < 4 => {
+            let item_type = read_u32(r)?;
+            let count = read_u64(r)?;
+            match scalar_size(item_type) {
+                Some(size) => skip(r, size.checked_mul(count).ok_or("Corrupt model header")?)?,
+                None => {
+                    for _ in 0..count {
+                        read_value(r, item_type, depth + 1)?;
+                    }
+                }
+            }
+            Ok(MetaValue::Skipped)
+        }
+        t => match scalar_size(t) {
+            Some(size) => skip(r, size).map(|_| MetaValue::Skipped),
+            None => Err(format!("Unknown GGUF value type {}", t)),
+        },
+    }
+}
+
+/// Parses the container header and, for GGUF, the metadata needed to validate the model
+pub fn read_header<Rd: Read + Seek>(r: &mut Rd) -> Result<ModelHeader, String> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic).map_err(|_| "File is too small to be a model".to_string())?;
+    if &magic != b"GGUF" {
+        let magic = u32::from_le_bytes(magic);
+        let (_, format) = LEGACY_MAGICS
+            .iter()
+            .find(|(m, _)| *m == magic)
+            .ok_or("Not a GGUF or GGML model file")?;
+        let version = if *format == "ggml" { None } else { Some(read_u32(r)?) };
+        return Ok(ModelHeader {
+            format: format.to_string(),
+            version,
+            architecture: None,
+            quantization: None,
+            context_length: None,
+            block_count: None,
+            embedding_length: None,
+            tensor_count: None,
+        });
+    }
+
+    let version = read_u32(r)?;
+    if !(2..=3).contains(&version) {
+        return Err(format!("Unsupported GGUF version {}", version));
+    }
+    let tensor_count = read_u64(r)?;
+    let kv_count = read_u64(r)?;
+    let mut strings: HashMap<String, String> = HashMap::new();
+    let mut numbers: HashMap<String, u64> = HashMap::new();
+    for _ in 0..kv_count {
+        let key = read_string(r, 1024)?.ok_or("Corrupt GGUF metadata key")?;
+        let value_type = read_u32(r)?;
+        match read_value(r, value_type, 0)? {
+            MetaValue::Uint(n) => {
+                numbers.insert(key, n);
+            }
+            MetaValue::Str(s) => {
+                strings.insert(key, s);
+            }
+            MetaValue::Skipped => {}
+        }
+    }
+
+    let architecture = strings.remove("general.architecture");
+    let arch_number = |key: &str| {
+        architecture
+            .as_ref()
+            .and_then(|arch| numbers.get(&format!("{}.{}", arch, key)).copied())
+    };
+    Ok(ModelHeader {
+        format: "gguf".to_string(),
+        version: Some(version),
+        quantization: numbers.get("general.file_type").map(|t| quantization_name(*t)),
+        context_length: arch_number("context_length"),
+        block_count: arch_number("block_count"),
+        embedding_length: arch_number("embedding_length"),
+        architecture,
+        tensor_count: Some(tensor_count),
+    })
+}
+
+/// Validates the file at `path` before it is loaded or imported for `entry`
+pub fn check_header(path: &Path, entry: &ModelEntry) -> Result<ModelHeader, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let header = read_header(&mut std::io::BufReader::new(file)).map_err(|e| format!("{:?}: {}", path, e))?;
+    if header.format == "gguf" {
+        if header.architecture.is_none() {
+            return Err(format!("{:?}: GGUF header has no general.architecture", path));
+        }
+        if header.tensor_count == Some(0) {
+            return Err(format!("{:?}: GGUF file contains no tensors", path));
+        }
+    }
+    if let Some(trained) = header.context_length {
+        if entry.context_size as u64 > trained {
+            return Err(format!(
+                "Model '{}' is configured for a {}-token context but the file supports {}",
+                entry.id, entry.context_size, trained
+            ));
+        }
+    }
+    Ok(header)
+}
+
+// ============ Integrity ============
+
+/// Streams the file through SHA-256, reporting bytes hashed so far
+pub fn sha256_file(path: &Path, mut on_progress: impl FnMut(u64)) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    let mut done = 0u64;
+    loop {
+        let n = file.read(&mut buf).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        done += n as u64;
+        on_progress(done);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CheckedFile {
+    size: u64,
+    modified: u64,
+    sha256: String,
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((metadata.len(), modified))
+}
+
+/// Hashes of model files, reused while the file's size and modification time are unchanged
+pub struct ModelFiles {
+    path: PathBuf,
+    checked: Mutex<HashMap<String, CheckedFile>>,
+    importing: Mutex<HashSet<String>>,
+}
+
+impl ModelFiles {
+    pub fn load(path: PathBuf) -> Self {
+        let checked = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            checked: Mutex::new(checked),
+            importing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn save(&self, checked: &HashMap<String, CheckedFile>) {
+        if let Some(parent) = self.path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Ok(json) = serde_json::to_string_pretty(checked) {
+            let _ = std::fs::write(&self.path, json);
+        }
+    }
+
+    /// The recorded hash of `file`, if it hasn't changed since
+    pub fn known_hash(&self, file: &Path) -> Option<String> {
+        let (size, modified) = file_stamp(file)?;
+        let checked = self.checked.lock().unwrap();
+        checked
+            .get(&file.to_string_lossy().to_string())
+            .filter(|c| c.size == size && c.modified == modified)
+            .map(|c| c.sha256.clone())
+    }
+
+    pub fn record_hash(&self, file: &Path, sha256: &str) {
+        let Some((size, modified)) = file_stamp(file) else { return };
+        let mut checked = self.checked.lock().unwrap();
+        checked.insert(
+            file.to_string_lossy().to_string(),
+            CheckedFile {
+                size,
+                modified,
+                sha256: sha256.to_string(),
+            },
+        );
+        self.save(&checked);
+    }
+
+    pub fn is_importing(&self, model_id: &str) -> bool {
+        self.importing.lock().unwrap().contains(model_id)
+    }
+}
+
+/// Clears the model's in-progress mark when the import ends, however it ends
+struct ImportGuard<'a> {
+    files: &'a ModelFiles,
+    model_id: String,
+}
+
+impl<'a> ImportGuard<'a> {
+    fn start(files: &'a ModelFiles, model_id: &str) -> Result<Self, String> {
+        if !files.importing.lock().unwrap().insert(model_id.to_string()) {
+            return Err(format!("Model '{}' is already being imported", model_id));
+        }
+        Ok(Self {
+            files,
+            model_id: model_id.to_string(),
+        })
+    }
+}
+
+impl Drop for ImportGuard<'_> {
+    fn drop(&mut self) {
+        self.files.importing.lock().unwrap().remove(&self.model_id);
+    }
+}
+
+// ============ Download ============
+
+/// Start offset and full size from a `Content-Range` header (`bytes 50-199/200`, `bytes */200`)
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = match range {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.trim().parse().ok()?),
+    };
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+/// `<target>.part`, where downloads and copies land until they are verified
+fn part_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    target.with_file_name(name)
+}
+
+/// Downloads `url` into `part`, continuing from the bytes already there.
+/// `on_progress` gets (bytes on disk, expected total).
+async fn download(url: &str, part: &Path, mut on_progress: impl FnMut(u64, Option<u64>)) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .user_agent("daavfx-launcher")
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::limited(MAX_REDIRECTS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let offset = tokio::fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let mut response = request.send().await.map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    let content_range = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_content_range);
+
+    let append = match response.status().as_u16() {
+        // The part file already holds the whole file, if it is as long as the mirror's copy
+        416 if offset > 0 => {
+            return match content_range.and_then(|(_, total)| total) {
+                Some(t) if t != offset => {
+                    let _ = tokio::fs::remove_file(part).await;
+                    Err(format!("Partial download has {} bytes but the file has {}; import again", offset, t))
+                }
+                _ => Ok(()),
+            };
+        }
+        206 if offset > 0 => {
+            if content_range.and_then(|(start, _)| start) != Some(offset) {
+                return Err("Mirror resumed at the wrong offset".to_string());
+            }
+            true
+        }
+        200 => false,
+        status => return Err(format!("Mirror answered HTTP {} for {}", status, url)),
+    };
+    let start = if append { offset } else { 0 };
+    let total = match (content_range.and_then(|(_, total)| total), response.content_length()) {
+        (Some(full), Some(remaining)) if full != start + remaining => {
+            return Err("Mirror sent a Content-Length that doesn't match its Content-Range".to_string());
+        }
+        (Some(full), _) => Some(full),
+        (None, remaining) => remaining.map(|r| r + start),
+    };
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+        .map_err(|e| format!("Failed to open {:?}: {}", part, e))?;
+    let mut written = start;
+    on_progress(written, total);
+    loop {
+        let chunk = response.chunk().await.map_err(|e| {
+            format!("Download interrupted at {} bytes ({}); import again to resume", written, e)
+        })?;
+        let Some(chunk) = chunk else {
+            break;
+        };
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        written += chunk.len() as u64;
+        on_progress(written, total);
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+
+    match total {
+        Some(t) if written < t => Err(format!(
+            "Download interrupted at {} of {} bytes; import again to resume",
+            written, t
+        )),
+        Some(t) if written > t => {
+            let _ = tokio::fs::remove_file(part).await;
+            Err(format!("Mirror sent {} bytes but announced {}", written, t))
+        }
+        _ => Ok(()),
+    }
+}
+
+// ============ Status ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileLocation {
+    Imported,
+    Bundled,
+    /// Absolute path from the registry
+    External,
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelFileStatus {
+    pub id: String,
+    pub path: PathBuf,
+    pub location: FileLocation,
+    pub size_bytes: Option<u64>,
+    pub expected_sha256: Option<String>,
+    /// Last computed hash; None until the file has been imported or verified
+    pub sha256: Option<String>,
+    /// None when either hash is unknown
+    pub verified: Option<bool>,
+    pub header: Option<ModelHeader>,
+    pub header_error: Option<String>,
+    /// Bytes of an interrupted download the next import resumes from
+    pub partial_bytes: Option<u64>,
+    pub importing: bool,
+}
+
+fn file_status(entry: &ModelEntry, dirs: &[PathBuf], files: &ModelFiles, verify: bool) -> ModelFileStatus {
+    let path = crate::ai_models::locate(dirs, &entry.path);
+    let size_bytes = std::fs::metadata(&path).map(|m| m.len()).ok();
+    let location = match size_bytes {
+        None => FileLocation::Missing,
+        Some(_) if entry.path.is_absolute() => FileLocation::External,
+        Some(_) if dirs.first().is_some_and(|d| path.starts_with(d)) => FileLocation::Imported,
+        Some(_) => FileLocation::Bundled,
+    };
+
+    let (mut header, mut header_error, mut sha256) = (None, None, None);
+    if size_bytes.is_some() {
+        match check_header(&path, entry) {
+            Ok(h) => header = Some(h),
+            Err(e) => header_error = Some(e),
+        }
+        sha256 = files.known_hash(&path);
+        if sha256.is_none() && verify {
+            if let Ok(hash) = sha256_file(&path, |_| {}) {
+                files.record_hash(&path, &hash);
+                sha256 = Some(hash);
+            }
+        }
+    }
+    let verified = match (&entry.sha256, &sha256) {
+        (Some(expected), Some(actual)) => Some(expected.eq_ignore_ascii_case(actual)),
+        _ => None,
+    };
+    let partial_bytes = dirs
+        .first()
+        .filter(|_| entry.path.is_relative())
+        .and_then(|dir| std::fs::metadata(part_path(&dir.join(&entry.path))).ok())
+        .map(|m| m.len());
+
+    ModelFileStatus {
+        id: entry.id.clone(),
+        path,
+        location,
+        size_bytes,
+        expected_sha256: entry.sha256.clone(),
+        sha256,
+        verified,
+        header,
+        header_error,
+        partial_bytes,
+        importing: files.is_importing(&entry.id),
+    }
+}
+
+// ============ Import ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStage {
+    Copying,
+    Downloading,
+    Verifying,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub model_id: String,
+    pub stage: ImportStage,
+    pub bytes: u64,
+    pub total: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Emits progress at most every `PROGRESS_INTERVAL`, plus every stage change
+struct Progress<R: Runtime> {
+    app: AppHandle<R>,
+    model_id: String,
+    last: Option<(ImportStage, Instant)>,
+}
+
+impl<R: Runtime> Progress<R> {
+    fn report(&mut self, stage: ImportStage, bytes: u64, total: Option<u64>, error: Option<String>) {
+        let due = match self.last {
+            Some((last_stage, at)) => last_stage != stage || at.elapsed() >= PROGRESS_INTERVAL || error.is_some(),
+            None => true,
+        };
+        if !due {
+            return;
+        }
+        self.last = Some((stage, Instant::now()));
+        let payload = ImportProgress {
+            model_id: self.model_id.clone(),
+            stage,
+            bytes,
+            total,
+            error,
+        };
+        crate::event_bus::emit(&self.app, "models", "model-import-progress", payload);
+    }
+}
+
+fn copy_file<R: Runtime>(source: &Path, part: &Path, progress: &mut Progress<R>) -> Result<(), String> {
+    let mut input = std::fs::File::open(source).map_err(|e| format!("Failed to open {:?}: {}", source, e))?;
+    let total = input.metadata().map(|m| m.len()).ok();
+    let mut output = std::fs::File::create(part).map_err(|e| format!("Failed to create {:?}: {}", part, e))?;
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    let mut copied = 0u64;
+    loop {
+        let n = input.read(&mut buf).map_err(|e| format!("Failed to read {:?}: {}", source, e))?;
+        if n == 0 {
+            break;
+        }
+        std::io::Write::write_all(&mut output, &buf[..n]).map_err(|e| format!("Failed to write {:?}: {}", part, e))?;
+        copied += n as u64;
+        progress.report(ImportStage::Copying, copied, total, None);
+    }
+    output.sync_all().map_err(|e| e.to_string())
+}
+
+/// Hashes and validates `part` for `entry`; a file that fails is deleted so the next import starts clean
+fn verify_part<R: Runtime>(part: &Path, entry: &ModelEntry, progress: &mut Progress<R>) -> Result<String, String> {
+    let total = std::fs::metadata(part).map(|m| m.len()).ok();
+    let result = sha256_file(part, |done| progress.report(ImportStage::Verifying, done, total, None)).and_then(|sha256| {
+        if let Some(expected) = &entry.sha256 {
+            if !expected.eq_ignore_ascii_case(&sha256) {
+                return Err(format!(
+                    "SHA-256 mismatch for '{}': expected {}, got {}",
+                    entry.id, expected, sha256
+                ));
+            }
+        }
+        check_header(part, entry)?;
+        Ok(sha256)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(part);
+    }
+    result
+}
+
+async fn import<R: Runtime>(
+    app: &AppHandle<R>,
+    ai_state: &AIState,
+    files: &ModelFiles,
+    entry: &ModelEntry,
+    source: &str,
+    progress: &mut Progress<R>,
+) -> Result<PathBuf, String> {
+    if entry.path.is_absolute() {
+        return Err(format!(
+            "Model '{}' points at an absolute path; only registry paths relative to the models folder can be imported",
+            entry.id
+        ));
+    }
+    let target = crate::ai_models::import_dir(app)?.join(&entry.path);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let part = part_path(&target);
+
+    if source.starts_with("http://") || source.starts_with("https://") {
+        // Without a length a truncated body looks complete; only the checksum catches it
+        if entry.sha256.is_none() {
+            return Err(format!(
+                "Model '{}' has no sha256 in models.json; add it before importing over HTTP",
+                entry.id
+            ));
+        }
+        download(source, &part, |bytes, total| progress.report(ImportStage::Downloading, bytes, total, None)).await?;
+    } else {
+        let source = PathBuf::from(source);
+        let part = part.clone();
+        let mut copy_progress = Progress {
+            app: progress.app.clone(),
+            model_id: progress.model_id.clone(),
+            last: None,
+        };
+        tokio::task::spawn_blocking(move || copy_file(&source, &part, &mut copy_progress))
+            .await
+            .map_err(|e| e.to_string())??;
+    }
+
+    let (verify_part_path, verify_entry) = (part.clone(), entry.clone());
+    let mut verify_progress = Progress {
+        app: progress.app.clone(),
+        model_id: progress.model_id.clone(),
+        last: None,
+    };
+    let sha256 = tokio::task::spawn_blocking(move || verify_part(&verify_part_path, &verify_entry, &mut verify_progress))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    // A resident copy of the old file would otherwise keep serving requests
+    let _ = ai_state.models.unload(&entry.id).await;
+    std::fs::rename(&part, &target).map_err(|e| format!("Failed to move {:?} into place: {}", part, e))?;
+    files.record_hash(&target, &sha256);
+    Ok(target)
+}
+
+// ============ Commands ============
+
+/// Imports the registry model `model_id` from a local file path or an `http(s)://` mirror URL
+#[tauri::command]
+pub async fn import_model<R: Runtime>(
+    app: AppHandle<R>,
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    files: tauri::State<'_, Arc<ModelFiles>>,
+    model_id: String,
+    source: String,
+) -> Result<ModelFileStatus, String> {
+    let entry = ai_state.models.config().find(Some(&model_id))?.clone();
+    let guard = ImportGuard::start(&files, &model_id)?;
+    let mut progress = Progress {
+        app: app.clone(),
+        model_id: model_id.clone(),
+        last: None,
+    };
+
+    match import(&app, &ai_state, &files, &entry, source.trim(), &mut progress).await {
+        Ok(target) => {
+            let size = std::fs::metadata(&target).map(|m| m.len()).ok();
+            progress.report(ImportStage::Done, size.unwrap_or(0), size, None);
+        }
+        Err(e) => {
+            progress.report(ImportStage::Failed, 0, None, Some(e.clone()));
+            return Err(e);
+        }
+    }
+
+    let dirs = crate::ai_models::model_dirs(&app)?;
+    let files = files.inner().clone();
+    drop(guard);
+    tauri::async_runtime::spawn_blocking(move || file_status(&entry, &dirs, &files, false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// File, header and checksum state of every registry model (or just `model_id`).
+/// `verify` hashes files whose checksum isn't known yet, which takes a while for large models.
+#[tauri::command]
+pub async fn model_status<R: Runtime>(
+    app: AppHandle<R>,
+    ai_state: tauri::State<'_, Arc<AIState>>,
+    files: tauri::State<'_, Arc<ModelFiles>>,
+    model_id: Option<String>,
+    verify: Option<bool>,
+) -> Result<Vec<ModelFileStatus>, String> {
+    let config = ai_state.models.config();
+    let entries: Vec<ModelEntry> = match &model_id {
+        Some(id) => vec![config.find(Some(id))?.clone()],
+        None => config.models.clone(),
+    };
+    let dirs = crate::ai_models::model_dirs(&app)?;
+    let files = files.inner().clone();
+    let verify = verify.unwrap_or(false);
+    tauri::async_runtime::spawn_blocking(move || {
+        entries.iter().map(|entry| file_status(entry, &dirs, &files, verify)).collect()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tokio::io::AsyncReadExt;
+
+    fn gguf_string(out: &mut Vec<u8>, s: &str) {
+        out.extend((s.len() as u64).to_le_bytes());
+        out.extend(s.as_bytes());
+    }
+
+    /// A GGUF v3 header with architecture, file type, context length, layer sizes and a skipped vocabulary
+    fn sample_gguf() -> Vec<u8> {
+        let mut out = b"GGUF".to_vec();
+        out.extend(3u32.to_le_bytes());
+        out.extend(291u64.to_le_bytes());
+        out.extend(6u64.to_le_bytes());
+        gguf_string(&mut out, "general.architecture");
+        out.extend(8u32.to_le_bytes());
+        gguf_string(&mut out, "qwen2");
+        gguf_string(&mut out, "tokenizer.ggml.tokens");
+        out.extend(9u32.to_le_bytes());
+        out.extend(8u32.to_le_bytes());
+        out.extend(2u64.to_le_bytes());
+        gguf_string(&mut out, "hello");
+        gguf_string(&mut out, "world");
+        gguf_string(&mut out, "general.file_type");
+        out.extend(4u32.to_le_bytes());
+        out.extend(15u32.to_le_bytes());
+        gguf_string(&mut out, "qwen2.context_length");
+        out.extend(4u32.to_le_bytes());
+        out.extend(32768u32.to_le_bytes());
+        gguf_string(&mut out, "qwen2.block_count");
+        out.extend(4u32.to_le_bytes());
+        out.extend(24u32.to_le_bytes());
+        gguf_string(&mut out, "qwen2.embedding_length");
+        out.extend(4u32.to_le_bytes());
+        out.extend(896u32.to_le_bytes());
+        out
+    }
+
+    fn entry(context_size: usize) -> ModelEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": "qwen", "path": "qwen.gguf", "context_size": context_size
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_gguf_header() {
+        let header = read_header(&mut Cursor::new(sample_gguf())).unwrap();
+        assert_eq!(header.format, "gguf");
+        assert_eq!(header.architecture.as_deref(), Some("qwen2"));
+        assert_eq!(header.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(header.context_length, Some(32768));
+        assert_eq!((header.block_count, header.embedding_length), (Some(24), Some(896)));
+        assert_eq!(header.tensor_count, Some(291));
+
+        let mut legacy = 0x6767_6a74u32.to_le_bytes().to_vec();
+        legacy.extend(3u32.to_le_bytes());
+        let header = read_header(&mut Cursor::new(legacy)).unwrap();
+        assert_eq!((header.format.as_str(), header.version), ("ggjt", Some(3)));
+
+        assert!(read_header(&mut Cursor::new(b"<html>".to_vec())).is_err());
+        assert!(read_header(&mut Cursor::new(sample_gguf()[..40].to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_check_header_and_hash() {
+        let dir = std::env::temp_dir().join("daavfx_model_files_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("qwen.gguf");
+        std::fs::write(&path, sample_gguf()).unwrap();
+
+        assert!(check_header(&path, &entry(2048)).is_ok());
+        let err = check_header(&path, &entry(65536)).unwrap_err();
+        assert!(err.contains("supports 32768"), "{}", err);
+
+        let files = ModelFiles::load(dir.join(CHECKS_FILE));
+        assert_eq!(files.known_hash(&path), None);
+        let hash = sha256_file(&path, |_| {}).unwrap();
+        assert_eq!(hash.len(), 64);
+        files.record_hash(&path, &hash);
+        assert_eq!(ModelFiles::load(dir.join(CHECKS_FILE)).known_hash(&path), Some(hash.clone()));
+
+        let mut expected = entry(2048);
+        expected.sha256 = Some(hash.to_uppercase());
+        let status = file_status(&expected, std::slice::from_ref(&dir), &files, false);
+        assert_eq!(status.location, FileLocation::Imported);
+        assert_eq!(status.verified, Some(true));
+        assert_eq!(status.header.unwrap().quantization.as_deref(), Some("Q4_K_M"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 50-199/200"), Some((Some(50), Some(200))));
+        assert_eq!(parse_content_range("bytes */200"), Some((None, Some(200))));
+        assert_eq!(parse_content_range("bytes 50-199/*"), Some((Some(50), None)));
+        assert_eq!(parse_content_range("items 1-2/3"), None);
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_with_range() {
+        let body: Vec<u8> = (0..200u8).collect();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = body.clone();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..n]).to_string();
+            assert!(request.to_ascii_lowercase().contains("range: bytes=50-"), "{}", request);
+            let head = "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 50-199/200\r\nContent-Length: 150\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&served[50..]).await.unwrap();
+        });
+
+        let dir = std::env::temp_dir().join("daavfx_model_download_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("m.gguf.part");
+        std::fs::write(&part, &body[..50]).unwrap();
+
+        let mut last = (0, None);
+        download(&format!("http://127.0.0.1:{}/m.gguf", port), &part, |bytes, total| last = (bytes, total))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), body);
+        assert_eq!(last, (200, Some(200)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_download_follows_relative_redirect_and_chunked_body() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 1024];
+                let n = socket.read(&mut request).await.unwrap();
+                let response: &[u8] = if request[..n].starts_with(b"GET /latest ") {
+                    b"HTTP/1.1 302 Found\r\nLocation: /files/m.gguf\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"
+                };
+                socket.write_all(response).await.unwrap();
+            }
+        });
+
+        let dir = std::env::temp_dir().join("daavfx_model_redirect_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("m.gguf.part");
+
+        let mut last = (0, None);
+        download(&format!("http://127.0.0.1:{}/latest", port), &part, |bytes, total| last = (bytes, total))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), b"hello world");
+        assert_eq!(last, (11, None));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_download_is_rejected() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            // Announces 300 bytes, then the connection drops after 100
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 300\r\n\r\n").await.unwrap();
+            socket.write_all(&[7u8; 100]).await.unwrap();
+        });
+
+        let dir = std::env::temp_dir().join("daavfx_model_truncated_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("m.gguf.part");
+
+        let err = download(&format!("http://127.0.0.1:{}/m.gguf", port), &part, |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(err.contains("import again to resume"), "{}", err);
+        assert_eq!(std::fs::metadata(&part).unwrap().len(), 100);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,628 @@
+//! Local model registry
+//! Models are described in `models.json` (id, file, architecture, tokenizer, prompt template,
+//! context size, expected SHA-256). Several can stay resident at once; the least recently used ones
+//! are evicted when loading another would exceed the memory budget. Cached chat sessions count
+//! against the same budget and go with their model.
+
+use crate::ai::CachedSession;
+use crate::ai_model_files::ModelHeader;
+use crate::ai_prompt::PromptTemplate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+
+const DEFAULT_MEMORY_BUDGET_MB: u64 = 4096;
+/// Chat sessions kept for their next turn; each holds a full-context KV cache
+pub const MAX_CACHED_SESSIONS: usize = 4;
+/// A cached session not used for this long is dropped
+pub const SESSION_IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TokenizerConfig {
+    /// Vocabulary stored in the model file
+    Embedded,
+    /// Hugging Face `tokenizer.json`; relative paths resolve like the model path
+    HuggingFace { path: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelEntry {
+    pub id: String,
+    /// Model file; relative paths resolve against the imported models, then the bundled `models/` folder
+    pub path: PathBuf,
+    /// llm architecture name (llama, gptneox, bloom, gpt2, gptj, mpt, falcon)
+    #[serde(default = "default_architecture")]
+    pub architecture: String,
+    #[serde(default = "default_tokenizer")]
+    pub tokenizer: TokenizerConfig,
+    #[serde(default)]
+    pub prompt_template: PromptTemplate,
+    #[serde(default = "default_context_size")]
+    pub context_size: usize,
+    /// Hex SHA-256 of the file; imports are rejected when it doesn't match. Required for HTTP imports.
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+fn default_architecture() -> String {
+    "llama".to_string()
+}
+
+fn default_tokenizer() -> TokenizerConfig {
+    TokenizerConfig::Embedded
+}
+
+fn default_context_size() -> usize {
+    2048
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    /// Model used when a request doesn't name one
+    pub default_model: String,
+    #[serde(default = "default_memory_budget_mb")]
+    pub memory_budget_mb: u64,
+    pub models: Vec<ModelEntry>,
+}
+
+fn default_memory_budget_mb() -> u64 {
+    DEFAULT_MEMORY_BUDGET_MB
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            default_model: "qwen2.5-0.5b".to_string(),
+            memory_budget_mb: DEFAULT_MEMORY_BUDGET_MB,
+            models: vec![ModelEntry {
+                id: "qwen2.5-0.5b".to_string(),
+                path: PathBuf::from("qwen2.5-0.5b-instruct-q4_k_m.gguf"),
+                architecture: default_architecture(),
+                tokenizer: default_tokenizer(),
+                prompt_template: PromptTemplate::ChatMl,
+                context_size: default_context_size(),
+                sha256: None,
+            }],
+        }
+    }
+}
+
+impl ModelConfig {
+    pub fn find(&self, id: Option<&str>) -> Result<&ModelEntry, String> {
+        let id = id.unwrap_or(&self.default_model);
+        self.models
+            .iter()
+            .find(|m| m.id == id)
+            .ok_or_else(|| format!("Unknown model '{}'", id))
+    }
+}
+
+/// The first of `dirs` holding `path`, or where it would be imported (`dirs[0]`) when none does
+pub fn locate(dirs: &[PathBuf], path: &Path) -> PathBuf {
+    if path.is_absolute() || dirs.is_empty() {
+        return path.to_path_buf();
+    }
+    dirs.iter()
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.exists())
+        .unwrap_or_else(|| dirs[0].join(path))
+}
+
+struct Resident<T> {
+    id: String,
+    value: T,
+    size_bytes: u64,
+    last_used: Instant,
+}
+
+/// Resident models with LRU eviction against a byte budget
+pub struct ResidentSet<T> {
+    entries: Vec<Resident<T>>,
+}
+
+impl<T> Default for ResidentSet<T> {
+    fn default() -> Self {
+        Self { entries: Vec::new() }
+    }
+}
+
+impl<T: Clone> ResidentSet<T> {
+    /// Returns a resident value and marks it as most recently used
+    pub fn get(&mut self, id: &str) -> Option<T> {
+        let entry = self.entries.iter_mut().find(|e| e.id == id)?;
+        entry.last_used = Instant::now();
+        Some(entry.value.clone())
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.iter().any(|e| e.id == id)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.size_bytes).sum()
+    }
+
+    /// Ids the next `insert` of `size_bytes` would evict, least recently used first.
+    /// A model larger than the whole budget evicts everything and is still loaded.
+    pub fn plan_eviction(&self, size_bytes: u64, budget_bytes: u64) -> Vec<String> {
+        let mut by_age: Vec<&Resident<T>> = self.entries.iter().collect();
+        by_age.sort_by_key(|e| e.last_used);
+        let mut total = self.total_bytes();
+        let mut evict = Vec::new();
+        for entry in by_age {
+            if total + size_bytes <= budget_bytes {
+                break;
+            }
+            total -= entry.size_bytes;
+            evict.push(entry.id.clone());
+        }
+        evict
+    }
+
+    /// Inserts a value, evicting least recently used entries to fit the budget. Returns evicted ids.
+    pub fn insert(&mut self, id: &str, value: T, size_bytes: u64, budget_bytes: u64) -> Vec<String> {
+        self.remove(id);
+        let evicted = self.plan_eviction(size_bytes, budget_bytes);
+        self.entries.retain(|e| !evicted.contains(&e.id));
+        self.entries.push(Resident {
+            id: id.to_string(),
+            value,
+            size_bytes,
+            last_used: Instant::now(),
+        });
+        evicted
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.id != id);
+        self.entries.len() != before
+    }
+
+    /// Like `get`, without marking the value as used
+    pub fn peek(&self, id: &str) -> Option<&T> {
+        self.entries.iter().find(|e| e.id == id).map(|e| &e.value)
+    }
+}
+
+struct CachedEntry<T> {
+    key: String,
+    model_id: String,
+    value: T,
+    size_bytes: u64,
+    last_used: Instant,
+}
+
+/// Inference sessions kept between chat turns, keyed by conversation id. Holds at most `capacity`
+/// sessions, least recently used out first, and drops those idle for longer than `idle_ttl`.
+pub struct SessionCache<T> {
+    entries: Vec<CachedEntry<T>>,
+    capacity: usize,
+    idle_ttl: Duration,
+}
+
+impl<T> Default for SessionCache<T> {
+    fn default() -> Self {
+        Self::new(MAX_CACHED_SESSIONS, SESSION_IDLE_TTL)
+    }
+}
+
+impl<T> SessionCache<T> {
+    pub fn new(capacity: usize, idle_ttl: Duration) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+            idle_ttl,
+        }
+    }
+
+    /// Removes and returns the session of `key`; it is out of the cache while in use
+    pub fn take(&mut self, key: &str) -> Option<T> {
+        let index = self.entries.iter().position(|e| e.key == key)?;
+        Some(self.entries.remove(index).value)
+    }
+
+    /// Stores the session of `key` for `model_id`, dropping the least recently used beyond `capacity`
+    pub fn put(&mut self, key: &str, model_id: &str, value: T, size_bytes: u64, now: Instant) {
+        self.remove(key);
+        self.entries.push(CachedEntry {
+            key: key.to_string(),
+            model_id: model_id.to_string(),
+            value,
+            size_bytes,
+            last_used: now,
+        });
+        while self.entries.len() > self.capacity {
+            self.pop_lru();
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.key != key);
+        self.entries.len() != before
+    }
+
+    /// Drops every session of a model that is being unloaded
+    pub fn remove_model(&mut self, model_id: &str) {
+        self.entries.retain(|e| e.model_id != model_id);
+    }
+
+    /// Drops sessions idle for longer than the TTL
+    pub fn expire(&mut self, now: Instant) {
+        let idle_ttl = self.idle_ttl;
+        self.entries.retain(|e| now.saturating_duration_since(e.last_used) < idle_ttl);
+    }
+
+    /// Drops the least recently used session. Returns false when the cache is empty.
+    pub fn pop_lru(&mut self) -> bool {
+        let oldest = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| e.last_used)
+            .map(|(i, _)| i);
+        match oldest {
+            Some(index) => {
+                self.entries.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.size_bytes).sum()
+    }
+}
+
+/// Memory of one session's KV cache: f16 keys and values for every layer and context position.
+/// Legacy GGML files carry no layer metadata; a quarter of the model file is assumed for those.
+pub fn session_bytes(header: &ModelHeader, context_size: usize, model_bytes: u64) -> u64 {
+    match (header.block_count, header.embedding_length) {
+        (Some(layers), Some(width)) => 2 * layers * width * context_size as u64 * 2,
+        _ => model_bytes / 4,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub path: PathBuf,
+    pub architecture: String,
+    pub prompt_template: String,
+    pub context_size: usize,
+    pub is_default: bool,
+    pub resident: bool,
+    pub file_exists: bool,
+    pub size_mb: u64,
+}
+
+/// A loaded model together with its registry entry
+#[derive(Clone)]
+pub struct LoadedModel {
+    pub entry: ModelEntry,
+    pub model: Arc<dyn llm::Model>,
+    /// Memory one cached chat session of this model holds
+    pub session_bytes: u64,
+}
+
+/// Model registry held by `AIState`. Starts with the built-in default until `load_config` runs.
+#[derive(Default)]
+pub struct ModelRegistry {
+    config: Mutex<ModelConfig>,
+    resident: tokio::sync::Mutex<ResidentSet<LoadedModel>>,
+    /// One lock per model id, held while that model loads
+    loading: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Chat sessions of resident models. Locked after `resident` when both are needed.
+    sessions: Mutex<SessionCache<CachedSession>>,
+}
+
+impl ModelRegistry {
+    /// Reads `models.json`, keeping the built-in default when it is missing or invalid
+    pub fn load_config(&self, path: &Path) {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return;
+        };
+        match serde_json::from_str::<ModelConfig>(&content) {
+            Ok(config) => *self.config.lock().unwrap() = config,
+            Err(e) => println!("Ignoring invalid {:?}, using the built-in model list: {}", path, e),
+        }
+    }
+
+    pub fn config(&self) -> ModelConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub async fn list(&self, dirs: &[PathBuf]) -> Vec<ModelInfo> {
+        let config = self.config();
+        let resident = self.resident.lock().await;
+        config
+            .models
+            .iter()
+            .map(|m| {
+                let path = locate(dirs, &m.path);
+                let size = std::fs::metadata(&path).map(|md| md.len()).ok();
+                ModelInfo {
+                    id: m.id.clone(),
+                    architecture: m.architecture.clone(),
+                    prompt_template: m.prompt_template.name().to_string(),
+                    context_size: m.context_size,
+                    is_default: m.id == config.default_model,
+                    resident: resident.contains(&m.id),
+                    file_exists: size.is_some(),
+                    size_mb: size.unwrap_or(0) / (1024 * 1024),
+                    path,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a resident model, loading it (and evicting others) if needed.
+    /// `id` None selects the configured default.
+    pub async fn get(&self, dirs: &[PathBuf], id: Option<&str>) -> Result<LoadedModel, String> {
+        let (entry, budget_bytes) = {
+            let config = self.config.lock().unwrap();
+            (config.find(id)?.clone(), config.memory_budget_mb * 1024 * 1024)
+        };
+
+        if let Some(loaded) = self.resident.lock().await.get(&entry.id) {
+            return Ok(loaded);
+        }
+
+        // Two requests for the same model wait for one load; resident models stay available meanwhile
+        let load_lock = self.loading.lock().unwrap().entry(entry.id.clone()).or_default().clone();
+        let _loading = load_lock.lock().await;
+        if let Some(loaded) = self.resident.lock().await.get(&entry.id) {
+            return Ok(loaded);
+        }
+
+        let path = locate(dirs, &entry.path);
+        let size_bytes = std::fs::metadata(&path).map(|m| m.len()).map_err(|_| {
+            format!("Model file for '{}' not found at {:?}. Import it with import_model.", entry.id, path)
+        })?;
+
+        // Free memory before loading the next model. Cached sessions are cheaper to rebuild, so they go first.
+        {
+            let mut resident = self.resident.lock().await;
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.expire(Instant::now());
+            while resident.total_bytes() + sessions.total_bytes() + size_bytes > budget_bytes && sessions.pop_lru() {}
+            for id in resident.plan_eviction(size_bytes + sessions.total_bytes(), budget_bytes) {
+                println!("Unloading model '{}' to stay within the memory budget", id);
+                resident.remove(&id);
+                sessions.remove_model(&id);
+            }
+        }
+
+        let load_entry = entry.clone();
+        let tokenizer_dirs = dirs.to_vec();
+        let (model, header) = tokio::task::spawn_blocking(move || {
+            let header = crate::ai_model_files::check_header(&path, &load_entry)?;
+            load_model_file(&load_entry, &path, &tokenizer_dirs).map(|model| (model, header))
+        })
+            .await
+            .map_err(|e| format!("Model load task failed: {}", e))??;
+
+        let loaded = LoadedModel {
+            session_bytes: session_bytes(&header, entry.context_size, size_bytes),
+            entry,
+            model,
+        };
+        let mut resident = self.resident.lock().await;
+        let mut sessions = self.sessions.lock().unwrap();
+        let model_budget = budget_bytes.saturating_sub(sessions.total_bytes());
+        for id in resident.insert(&loaded.entry.id, loaded.clone(), size_bytes, model_budget) {
+            sessions.remove_model(&id);
+        }
+        Ok(loaded)
+    }
+
+    pub async fn unload(&self, id: &str) -> Result<(), String> {
+        let mut resident = self.resident.lock().await;
+        if resident.remove(id) {
+            self.sessions.lock().unwrap().remove_model(id);
+            Ok(())
+        } else {
+            Err(format!("Model '{}' is not loaded", id))
+        }
+    }
+
+    /// Takes the cached session of a conversation for its next turn
+    pub fn take_session(&self, key: &str) -> Option<CachedSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.expire(Instant::now());
+        sessions.take(key)
+    }
+
+    /// Keeps a conversation's session for its next turn, unless its model was unloaded meanwhile.
+    /// Older sessions make room to stay within the budget; models are never evicted for a session.
+    pub async fn keep_session(&self, key: &str, loaded: &LoadedModel, session: CachedSession) {
+        let budget_bytes = self.config.lock().unwrap().memory_budget_mb * 1024 * 1024;
+        let resident = self.resident.lock().await;
+        let same_model = resident
+            .peek(&loaded.entry.id)
+            .is_some_and(|m| Arc::ptr_eq(&m.model, &loaded.model));
+        if !same_model {
+            return;
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.expire(Instant::now());
+        sessions.put(key, &loaded.entry.id, session, loaded.session_bytes, Instant::now());
+        while resident.total_bytes() + sessions.total_bytes() > budget_bytes && sessions.pop_lru() {}
+    }
+
+    pub fn drop_session(&self, key: &str) {
+        self.sessions.lock().unwrap().remove(key);
+    }
+
+    /// Drops sessions idle for longer than `SESSION_IDLE_TTL`
+    pub fn expire_sessions(&self) {
+        self.sessions.lock().unwrap().expire(Instant::now());
+    }
+}
+
+// ============ Loading ============
+
+fn load_model_file(entry: &ModelEntry, path: &Path, dirs: &[PathBuf]) -> Result<Arc<dyn llm::Model>, String> {
+    let architecture: llm::ModelArchitecture = entry
+        .architecture
+        .parse()
+        .map_err(|e| format!("Model '{}': {}", entry.id, e))?;
+    let tokenizer_source = match &entry.tokenizer {
+        TokenizerConfig::Embedded => llm::TokenizerSource::Embedded,
+        TokenizerConfig::HuggingFace { path } => {
+            llm::TokenizerSource::HuggingFaceTokenizerFile(locate(dirs, path))
+        }
+    };
+    let params = llm::ModelParameters {
+        context_size: entry.context_size,
+        ..Default::default()
+    };
+
+    let model = llm::load_dynamic(Some(architecture), path, tokenizer_source, params, |_| {})
+        .map_err(|e| format!("Failed to load model: {}", e))?;
+    Ok(Arc::from(model))
+}
+
+/// Folders relative model paths resolve against: imported models, then the bundled resources
+pub fn model_dirs<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<PathBuf>, String> {
+    Ok(vec![
+        import_dir(app)?,
+        app.path().resource_dir().map_err(|e| e.to_string())?.join("models"),
+    ])
+}
+
+/// Where `import_model` puts model files
+pub fn import_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    Ok(app.path().app_data_dir().map_err(|e| e.to_string())?.join("models"))
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub async fn list_models<R: Runtime>(
+    app: AppHandle<R>,
+    state: tauri::State<'_, Arc<crate::ai::AIState>>,
+) -> Result<Vec<ModelInfo>, String> {
+    Ok(state.models.list(&model_dirs(&app)?).await)
+}
+
+#[tauri::command]
+pub async fn load_model<R: Runtime>(
+    app: AppHandle<R>,
+    state: tauri::State<'_, Arc<crate::ai::AIState>>,
+    model_id: String,
+) -> Result<(), String> {
+    state.models.get(&model_dirs(&app)?, Some(&model_id)).await.map(|_| ())
+}
+
+#[tauri::command]
+pub async fn unload_model(
+    state: tauri::State<'_, Arc<crate::ai::AIState>>,
+    model_id: String,
+) -> Result<(), String> {
+    state.models.unload(&model_id).await
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut set: ResidentSet<u32> = ResidentSet::default();
+        assert!(set.insert("a", 1, 40, 100).is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(set.insert("b", 2, 40, 100).is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        // Touch "a" so "b" becomes the least recently used
+        assert_eq!(set.get("a"), Some(1));
+        assert_eq!(set.insert("c", 3, 40, 100), vec!["b".to_string()]);
+        assert!(set.contains("a") && set.contains("c"));
+        assert_eq!(set.total_bytes(), 80);
+
+        // Larger than the budget: everything goes
+        let mut evicted = set.insert("huge", 4, 500, 100);
+        evicted.sort();
+        assert_eq!(evicted, vec!["a".to_string(), "c".to_string()]);
+        assert!(set.remove("huge"));
+        assert!(!set.remove("huge"));
+    }
+
+    #[test]
+    fn test_session_cache() {
+        let start = Instant::now();
+        let mut cache: SessionCache<u32> = SessionCache::new(2, Duration::from_secs(60));
+        cache.put("c1", "qwen", 1, 10, start);
+        cache.put("c2", "qwen", 2, 10, start + Duration::from_secs(1));
+        cache.put("c3", "mistral", 3, 30, start + Duration::from_secs(2));
+        assert_eq!(cache.len(), 2, "least recently used goes beyond the capacity");
+        assert!(cache.take("c1").is_none());
+        assert_eq!(cache.total_bytes(), 40);
+
+        // In use on a worker, then back
+        assert_eq!(cache.take("c2"), Some(2));
+        cache.put("c2", "qwen", 2, 10, start + Duration::from_secs(3));
+        cache.remove_model("mistral");
+        assert_eq!((cache.len(), cache.total_bytes()), (1, 10));
+
+        cache.expire(start + Duration::from_secs(62));
+        assert_eq!(cache.len(), 1);
+        cache.expire(start + Duration::from_secs(63));
+        assert!(cache.is_empty());
+        assert!(!cache.pop_lru());
+    }
+
+    #[test]
+    fn test_session_bytes() {
+        let mut header = ModelHeader {
+            format: "gguf".to_string(),
+            version: Some(3),
+            architecture: Some("llama".to_string()),
+            quantization: None,
+            context_length: None,
+            block_count: Some(32),
+            embedding_length: Some(4096),
+            tensor_count: Some(291),
+        };
+        assert_eq!(session_bytes(&header, 2048, 0), 1024 * 1024 * 1024);
+        header.block_count = None;
+        assert_eq!(session_bytes(&header, 2048, 4000), 1000);
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: ModelConfig = serde_json::from_str(
+            r#"{"default_model": "mistral", "models": [
+                {"id": "mistral", "path": "mistral-7b.Q4_K_M.gguf", "prompt_template": "mistral"},
+                {"id": "phi", "path": "/opt/models/phi-2.gguf", "tokenizer": {"kind": "hugging_face", "path": "phi.json"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.memory_budget_mb, DEFAULT_MEMORY_BUDGET_MB);
+        assert_eq!(config.find(None).unwrap().id, "mistral");
+        assert_eq!(config.find(None).unwrap().prompt_template, PromptTemplate::Mistral);
+        assert_eq!(config.find(Some("phi")).unwrap().prompt_template, PromptTemplate::ChatMl);
+        assert!(config.find(Some("nope")).is_err());
+        assert_eq!(
+            locate(&[PathBuf::from("/res/models")], &config.find(None).unwrap().path),
+            PathBuf::from("/res/models/mistral-7b.Q4_K_M.gguf")
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! OpenAI-compatible API on the local AI server
+//! `/v1/models`, `/v1/chat/completions` and `/v1/completions` accept the OpenAI request schema
+//! (including `stream: true` over Server-Sent Events) so off-the-shelf clients work unchanged.
+
+use crate::ai::{AIState, AiResponse};
+use crate::ai_params::GenerationOptions;
+use crate::ai_prompt::{ChatMessage, Role};
+use crate::ai_worker::{WorkerError, DEFAULT_TIMEOUT};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tauri::AppHandle;
+
+/// `"text"` or `["a", "b"]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+/// Message content as a string or as a list of `{"type": "text", "text": ...}` parts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContentPart {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl MessageContent {
+    fn text(self) -> String {
+        match self {
+            MessageContent::Text(s) => s,
+            MessageContent::Parts(parts) => parts
+                .into_iter()
+                .filter(|p| p.kind == "text")
+                .filter_map(|p| p.text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenAiMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+}
+
+/// Sampling fields shared by both completion endpoints
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SamplingFields {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    /// Newer clients send this instead of `max_tokens`
+    #[serde(default)]
+    pub max_completion_tokens: Option<usize>,
+    #[serde(default)]
+    pub stop: Option<OneOrMany>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+impl SamplingFields {
+    fn options(&self) -> GenerationOptions {
+        GenerationOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_completion_tokens.or(self.max_tokens),
+            seed: self.seed,
+            stop: self.stop.clone().map(OneOrMany::into_vec).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionRequest {
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<OpenAiMessage>,
+    #[serde(flatten)]
+    pub sampling: SamplingFields,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionRequest {
+    #[serde(default)]
+    pub model: String,
+    pub prompt: OneOrMany,
+    #[serde(flatten)]
+    pub sampling: SamplingFields,
+}
+
+/// Maps OpenAI roles onto ours; `developer` is the newer name for `system`
+pub fn convert_messages(messages: Vec<OpenAiMessage>) -> Result<Vec<ChatMessage>, String> {
+    messages
+        .into_iter()
+        .map(|m| {
+            let role = match m.role.as_str() {
+                "system" | "developer" => Role::System,
+                "user" => Role::User,
+                "assistant" => Role::Assistant,
+                other => return Err(format!("Unsupported message role: {}", other)),
+            };
+            Ok(ChatMessage::new(role, m.content.map(MessageContent::text).unwrap_or_default()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct OpenAiUsage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
+
+fn usage(response: &AiResponse) -> OpenAiUsage {
+    OpenAiUsage {
+        prompt_tokens: response.usage.prompt_tokens,
+        completion_tokens: response.usage.generated_tokens,
+        total_tokens: response.usage.prompt_tokens + response.usage.generated_tokens,
+    }
+}
+
+fn finish_reason(response: &AiResponse, options: &GenerationOptions) -> &'static str {
+    if response.usage.generated_tokens >= options.max_tokens() {
+        "length"
+    } else {
+        "stop"
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Error body in OpenAI's `{"error": {...}}` shape
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    let kind = if status.is_client_error() { "invalid_request_error" } else { "server_error" };
+    (status, axum::Json(json!({ "error": { "message": message.into(), "type": kind, "code": null } }))).into_response()
+}
+
+fn worker_error(e: WorkerError) -> Response {
+    match e {
+        WorkerError::Busy { retry_after_secs } => {
+            let mut response = error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string());
+            if let Ok(value) = retry_after_secs.to_string().parse() {
+                response.headers_mut().insert(axum::http::header::RETRY_AFTER, value);
+            }
+            response
+        }
+        _ => error_response(e.status(), e.to_string()),
+    }
+}
+
+/// What to generate from
+enum Input {
+    Chat(Vec<ChatMessage>),
+    Text(String),
+}
+
+fn execute(
+    loaded: &crate::ai_models::LoadedModel,
+    input: &Input,
+    options: &GenerationOptions,
+    cancel: &AtomicBool,
+    on_token: impl FnMut(&str) -> bool,
+) -> Result<AiResponse, String> {
+    match input {
+        Input::Chat(messages) => crate::ai::infer_blocking(
+            loaded.model.as_ref(),
+            &loaded.entry.prompt_template,
+            messages,
+            options,
+            cancel,
+            on_token,
+        ),
+        Input::Text(prompt) => crate::ai::complete_blocking(loaded.model.as_ref(), prompt, options, cancel, on_token),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Endpoint {
+    Chat,
+    Completion,
+}
+
+impl Endpoint {
+    fn id_prefix(self) -> &'static str {
+        match self {
+            Endpoint::Chat => "chatcmpl",
+            Endpoint::Completion => "cmpl",
+        }
+    }
+
+    fn body(self, id: &str, model: &str, created: i64, response: &AiResponse, options: &GenerationOptions) -> serde_json::Value {
+        let finish = finish_reason(response, options);
+        match self {
+            Endpoint::Chat => json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": created,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": response.response },
+                    "finish_reason": finish,
+                }],
+                "usage": usage(response),
+            }),
+            Endpoint::Completion => json!({
+                "id": id,
+                "object": "text_completion",
+                "created": created,
+                "model": model,
+                "choices": [{ "index": 0, "text": response.response, "logprobs": null, "finish_reason": finish }],
+                "usage": usage(response),
+            }),
+        }
+    }
+
+    /// One streamed chunk; `finish` is set on the last one
+    fn chunk(self, id: &str, model: &str, created: i64, text: &str, finish: Option<&str>) -> serde_json::Value {
+        match self {
+            Endpoint::Chat => json!({
+                "id": id,
+                "object": "chat.completion.chunk",
+                "created": created,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "delta": if finish.is_some() { json!({}) } else { json!({ "content": text }) },
+                    "finish_reason": finish,
+                }],
+            }),
+            Endpoint::Completion => json!({
+                "id": id,
+                "object": "text_completion",
+                "created": created,
+                "model": model,
+                "choices": [{ "index": 0, "text": text, "logprobs": null, "finish_reason": finish }],
+            }),
+        }
+    }
+}
+
+async fn complete(
+    state: Arc<(AppHandle, Arc<AIState>)>,
+    endpoint: Endpoint,
+    requested_model: String,
+    input: Input,
+    sampling: SamplingFields,
+) -> Response {
+    let (app, ai_state) = &*state;
+    let options = sampling.options();
+    if let Err(e) = options.validate() {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+
+    // Clients send names like "gpt-4o"; anything not in the registry gets the default model
+    let config = ai_state.models.config();
+    let model_id = config.models.iter().any(|m| m.id == requested_model).then_some(requested_model.as_str());
+    let loaded = match crate::ai::get_or_load_model(app, ai_state, model_id).await {
+        Ok(loaded) => loaded,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let id = format!("{}-{}", endpoint.id_prefix(), crate::ai::new_request_id());
+    let model = loaded.entry.id.clone();
+    let created = unix_now();
+
+    if !sampling.stream {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let job_options = options.clone();
+        let result = ai_state
+            .workers
+            .run(cancel, DEFAULT_TIMEOUT, move || execute(&loaded, &input, &job_options, &flag, |_| true))
+            .await;
+        return match result {
+            Ok(Ok(response)) => axum::Json(endpoint.body(&id, &model, created, &response, &options)).into_response(),
+            Ok(Err(e)) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+            Err(e) => worker_error(e),
+        };
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(64);
+    if let Endpoint::Chat = endpoint {
+        let opening = json!({
+            "id": id, "object": "chat.completion.chunk", "created": created, "model": model,
+            "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "" }, "finish_reason": null }],
+        });
+        let _ = tx.try_send(Event::default().data(opening.to_string()));
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = cancel.clone();
+    let token_tx = tx.clone();
+    let (job_id, job_model, job_options) = (id.clone(), model.clone(), options.clone());
+    let job = ai_state.workers.enqueue(cancel, move || {
+        // A closed connection makes the send fail, which halts generation
+        execute(&loaded, &input, &job_options, &flag, |token| {
+            let chunk = endpoint.chunk(&job_id, &job_model, created, token, None);
+            token_tx.blocking_send(Event::default().data(chunk.to_string())).is_ok()
+        })
+    });
+    let job = match job {
+        Ok(job) => job,
+        Err(e) => return worker_error(e),
+    };
+
+    tokio::spawn(async move {
+        let result = job.wait(DEFAULT_TIMEOUT).await.map_err(String::from).and_then(|r| r);
+        let last = match result {
+            Ok(response) => endpoint.chunk(&id, &model, created, "", Some(finish_reason(&response, &options))),
+            Err(e) => json!({ "error": { "message": e, "type": "server_error", "code": null } }),
+        };
+        let _ = tx.send(Event::default().data(last.to_string())).await;
+        let _ = tx.send(Event::default().data("[DONE]")).await;
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok::<_, std::convert::Infallible>(event), rx))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+// ============ Handlers ============
+
+type ServerState = axum::extract::State<Arc<(AppHandle, Arc<AIState>)>>;
+
+pub async fn models_handler(axum::extract::State(state): ServerState) -> Response {
+    let (_, ai_state) = &*state;
+    let data: Vec<serde_json::Value> = ai_state
+        .models
+        .config()
+        .models
+        .iter()
+        .map(|m| json!({ "id": m.id, "object": "model", "created": 0, "owned_by": "local" }))
+        .collect();
+    axum::Json(json!({ "object": "list", "data": data })).into_response()
+}
+
+pub async fn chat_completions_handler(
+    axum::extract::State(state): ServerState,
+    axum::Json(request): axum::Json<ChatCompletionRequest>,
+) -> Response {
+    let messages = match convert_messages(request.messages) {
+        Ok(messages) if !messages.is_empty() => messages,
+        Ok(_) => return error_response(StatusCode::BAD_REQUEST, "messages must not be empty"),
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    complete(state, Endpoint::Chat, request.model, Input::Chat(messages), request.sampling).await
+}
+
+pub async fn completions_handler(
+    axum::extract::State(state): ServerState,
+    axum::Json(request): axum::Json<CompletionRequest>,
+) -> Response {
+    // Batched prompts aren't supported; several prompts are answered as one
+    let prompt = request.prompt.into_vec().join("\n");
+    complete(state, Endpoint::Completion, request.model, Input::Text(prompt), request.sampling).await
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chat_request() {
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{
+                "model": "gpt-4o-mini",
+                "messages": [
+                    {"role": "developer", "content": "Be brief."},
+                    {"role": "user", "content": [{"type": "text", "text": "Drawdown?"}, {"type": "image_url"}]}
+                ],
+                "temperature": 0.2,
+                "max_tokens": 64,
+                "stop": "\n\n",
+                "stream": true
+            }"#,
+        )
+        .unwrap();
+        assert!(request.sampling.stream);
+        let options = request.sampling.options();
+        assert_eq!(options.max_tokens, Some(64));
+        assert_eq!(options.stop, vec!["\n\n"]);
+
+        let messages = convert_messages(request.messages).unwrap();
+        assert_eq!(messages[0], ChatMessage::new(Role::System, "Be brief."));
+        assert_eq!(messages[1], ChatMessage::new(Role::User, "Drawdown?"));
+
+        let bad = vec![OpenAiMessage { role: "tool".into(), content: None }];
+        assert!(convert_messages(bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_completion_request() {
+        let request: CompletionRequest =
+            serde_json::from_str(r#"{"prompt": ["a", "b"], "max_completion_tokens": 8, "stop": ["x", "y"]}"#).unwrap();
+        assert_eq!(request.model, "");
+        assert!(!request.sampling.stream);
+        assert_eq!(request.sampling.options().max_tokens, Some(8));
+        assert_eq!(request.prompt.into_vec(), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,170 @@
+//! Generation options and usage reporting for AI requests
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_MAX_TOKENS: usize = 1024;
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Sampling and length settings a caller may override; unset fields keep the model defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    /// 0 picks the most likely token every time
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    /// Number of previous tokens the repeat penalty looks at
+    #[serde(default)]
+    pub repeat_last_n: Option<usize>,
+    /// Fixed RNG seed for reproducible output
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    /// Extra stop sequences on top of the prompt template's own
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+impl GenerationOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(t) = self.temperature {
+            if !(0.0..=2.0).contains(&t) {
+                return Err("temperature must be between 0 and 2".to_string());
+            }
+        }
+        if self.top_k == Some(0) {
+            return Err("top_k must be at least 1".to_string());
+        }
+        if let Some(p) = self.top_p {
+            if !(p > 0.0 && p <= 1.0) {
+                return Err("top_p must be greater than 0 and at most 1".to_string());
+            }
+        }
+        if let Some(penalty) = self.repeat_penalty {
+            if !(penalty > 0.0 && penalty <= 2.0) {
+                return Err("repeat_penalty must be greater than 0 and at most 2".to_string());
+            }
+        }
+        if let Some(max) = self.max_tokens {
+            if max == 0 || max > 8192 {
+                return Err("max_tokens must be between 1 and 8192".to_string());
+            }
+        }
+        if self.stop.len() > MAX_STOP_SEQUENCES || self.stop.iter().any(|s| s.is_empty()) {
+            return Err(format!("stop accepts up to {} non-empty sequences", MAX_STOP_SEQUENCES));
+        }
+        Ok(())
+    }
+
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+    }
+
+    /// Sampler overrides in llm's `name:key=value` syntax
+    pub fn sampler_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        match self.temperature {
+            // Greedy: only the most likely token survives
+            Some(0.0) => args.push("topk:k=1".to_string()),
+            Some(t) => args.push(format!("temperature:temperature={}", t)),
+            None => {}
+        }
+        // Greedy already fixed top-k
+        if let Some(k) = self.top_k.filter(|_| self.temperature != Some(0.0)) {
+            args.push(format!("topk:k={}", k));
+        }
+        if let Some(p) = self.top_p {
+            args.push(format!("topp:p={}", p));
+        }
+        match (self.repeat_penalty, self.repeat_last_n) {
+            (Some(penalty), Some(n)) => args.push(format!("repetition:penalty={}:last_n={}", penalty, n)),
+            (Some(penalty), None) => args.push(format!("repetition:penalty={}", penalty)),
+            (None, Some(n)) => args.push(format!("repetition:last_n={}", n)),
+            (None, None) => {}
+        }
+        args
+    }
+}
+
+/// Token counts and timings of one generation
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub generated_tokens: usize,
+    /// Prompt evaluation plus the first generated token
+    pub time_to_first_token_ms: Option<u64>,
+    pub total_ms: u64,
+    /// Generation speed after the first token
+    pub tokens_per_sec: f64,
+}
+
+impl Usage {
+    pub fn new(prompt_tokens: usize, generated_tokens: usize, first_token: Option<Duration>, total: Duration) -> Self {
+        let generating = total.saturating_sub(first_token.unwrap_or_default()).as_secs_f64();
+        let tokens_per_sec = if generated_tokens > 1 && generating > 0.0 {
+            (generated_tokens - 1) as f64 / generating
+        } else {
+            0.0
+        };
+        Self {
+            prompt_tokens,
+            generated_tokens,
+            time_to_first_token_ms: first_token.map(|d| d.as_millis() as u64),
+            total_ms: total.as_millis() as u64,
+            tokens_per_sec,
+        }
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_and_sampler_args() {
+        assert!(GenerationOptions::default().validate().is_ok());
+        assert!(GenerationOptions::default().sampler_args().is_empty());
+
+        let options = GenerationOptions {
+            temperature: Some(0.7),
+            top_k: Some(40),
+            top_p: Some(0.9),
+            repeat_penalty: Some(1.1),
+            repeat_last_n: Some(64),
+            ..Default::default()
+        };
+        assert!(options.validate().is_ok());
+        assert_eq!(
+            options.sampler_args(),
+            vec!["temperature:temperature=0.7", "topk:k=40", "topp:p=0.9", "repetition:penalty=1.1:last_n=64"]
+        );
+
+        let greedy = GenerationOptions { temperature: Some(0.0), top_k: Some(40), ..Default::default() };
+        assert_eq!(greedy.sampler_args(), vec!["topk:k=1"]);
+
+        for bad in [
+            GenerationOptions { temperature: Some(3.0), ..Default::default() },
+            GenerationOptions { top_p: Some(0.0), ..Default::default() },
+            GenerationOptions { max_tokens: Some(0), ..Default::default() },
+            GenerationOptions { stop: vec![String::new()], ..Default::default() },
+        ] {
+            assert!(bad.validate().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_usage_rates() {
+        let usage = Usage::new(12, 21, Some(Duration::from_millis(500)), Duration::from_millis(2500));
+        assert_eq!(usage.time_to_first_token_ms, Some(500));
+        assert_eq!(usage.tokens_per_sec, 10.0);
+        assert_eq!(Usage::new(12, 0, None, Duration::from_millis(100)).tokens_per_sec, 0.0);
+    }
+}
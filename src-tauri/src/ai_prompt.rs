@@ -0,0 +1,292 @@
+//! Prompt templates
+//! Turns a conversation into the prompt format a model was trained on and knows the
+//! template's end-of-turn markers, so generation stops there instead of running to the token limit.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+
+    /// System context plus one question, the shape of `AiRequest`
+    pub fn single_turn(system_context: &str, prompt: &str) -> Vec<ChatMessage> {
+        let mut messages = Vec::new();
+        if !system_context.is_empty() {
+            messages.push(ChatMessage::new(Role::System, system_context));
+        }
+        messages.push(ChatMessage::new(Role::User, prompt));
+        messages
+    }
+}
+
+/// User-defined template. Each format contains a `{content}` placeholder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomTemplate {
+    #[serde(default = "default_system_format")]
+    pub system: String,
+    pub user: String,
+    pub assistant: String,
+    /// Appended after the last message to start the model's answer
+    #[serde(default)]
+    pub generation_prefix: String,
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+fn default_system_format() -> String {
+    "{content}\n\n".to_string()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptTemplate {
+    /// `<|im_start|>role ... <|im_end|>` (Qwen, OpenHermes, Yi)
+    #[default]
+    #[serde(rename = "chatml")]
+    ChatMl,
+    /// `[INST] <<SYS>> ... <</SYS>> ... [/INST]`
+    #[serde(rename = "llama2")]
+    Llama2,
+    /// `### Instruction: ... ### Response:`
+    Alpaca,
+    /// `[INST] ... [/INST]` without a system block
+    Mistral,
+    /// Message contents joined by newlines, no markers
+    Raw,
+    Custom(CustomTemplate),
+}
+
+impl PromptTemplate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PromptTemplate::ChatMl => "chatml",
+            PromptTemplate::Llama2 => "llama2",
+            PromptTemplate::Alpaca => "alpaca",
+            PromptTemplate::Mistral => "mistral",
+            PromptTemplate::Raw => "raw",
+            PromptTemplate::Custom(_) => "custom",
+        }
+    }
+
+    /// Text that ends the assistant's turn; generation halts when one appears
+    pub fn stop_sequences(&self) -> Vec<String> {
+        let stops: &[&str] = match self {
+            PromptTemplate::ChatMl => &["<|im_end|>", "<|im_start|>"],
+            PromptTemplate::Llama2 | PromptTemplate::Mistral => &["</s>", "[INST]"],
+            PromptTemplate::Alpaca => &["### Instruction:", "### Input:"],
+            PromptTemplate::Raw => &[],
+            PromptTemplate::Custom(custom) => return custom.stop.clone(),
+        };
+        stops.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Renders the conversation, ending where the assistant's next answer begins
+    pub fn render(&self, messages: &[ChatMessage]) -> String {
+        match self {
+            PromptTemplate::ChatMl => {
+                let mut out = String::new();
+                for m in messages {
+                    out.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", m.role.as_str(), m.content));
+                }
+                out.push_str("<|im_start|>assistant\n");
+                out
+            }
+            PromptTemplate::Llama2 | PromptTemplate::Mistral => self.render_inst(messages),
+            PromptTemplate::Alpaca => {
+                let mut out = String::new();
+                for m in messages {
+                    match m.role {
+                        Role::System => out.push_str(&format!("{}\n\n", m.content)),
+                        Role::User => out.push_str(&format!("### Instruction:\n{}\n\n", m.content)),
+                        Role::Assistant => out.push_str(&format!("### Response:\n{}\n\n", m.content)),
+                    }
+                }
+                out.push_str("### Response:\n");
+                out
+            }
+            PromptTemplate::Raw => {
+                let mut out = messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n");
+                out.push('\n');
+                out
+            }
+            PromptTemplate::Custom(custom) => {
+                let mut out = String::new();
+                for m in messages {
+                    let format = match m.role {
+                        Role::System => &custom.system,
+                        Role::User => &custom.user,
+                        Role::Assistant => &custom.assistant,
+                    };
+                    out.push_str(&format.replace("{content}", &m.content));
+                }
+                out.push_str(&custom.generation_prefix);
+                out
+            }
+        }
+    }
+
+    /// `[INST]` family. The system prompt is folded into the first user turn
+    /// (inside `<<SYS>>` for Llama-2, plain text for Mistral which has no system role).
+    fn render_inst(&self, messages: &[ChatMessage]) -> String {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        let mut system = Some(system.join("\n")).filter(|s| !s.is_empty());
+
+        let mut out = String::new();
+        let mut open_turn = false;
+        for m in messages.iter().filter(|m| m.role != Role::System) {
+            match m.role {
+                Role::User => {
+                    if open_turn {
+                        // Llama-2 opens every later turn with a fresh BOS; Mistral does not
+                        out.push_str(if *self == PromptTemplate::Llama2 { "</s><s>" } else { "</s>" });
+                    }
+                    let content = match (system.take(), self) {
+                        (Some(sys), PromptTemplate::Llama2) => format!("<<SYS>>\n{}\n<</SYS>>\n\n{}", sys, m.content),
+                        (Some(sys), _) => format!("{}\n\n{}", sys, m.content),
+                        (None, _) => m.content.clone(),
+                    };
+                    out.push_str(&format!("[INST] {} [/INST]", content));
+                    open_turn = true;
+                }
+                _ => out.push_str(&format!(" {}", m.content)),
+            }
+        }
+        out
+    }
+}
+
+/// Holds back streamed text that might be the start of a stop sequence,
+/// so markers like `<|im_end|>` never reach the caller even when split across tokens
+pub struct StopMatcher {
+    stops: Vec<String>,
+    pending: String,
+}
+
+impl StopMatcher {
+    pub fn new(stops: Vec<String>) -> Self {
+        Self {
+            stops: stops.into_iter().filter(|s| !s.is_empty()).collect(),
+            pending: String::new(),
+        }
+    }
+
+    /// Adds a token; returns the text safe to emit and whether a stop sequence was reached
+    pub fn push(&mut self, token: &str) -> (String, bool) {
+        self.pending.push_str(token);
+
+        if let Some(pos) = self.stops.iter().filter_map(|s| self.pending.find(s.as_str())).min() {
+            let out = self.pending[..pos].to_string();
+            self.pending.clear();
+            return (out, true);
+        }
+
+        // Keep the longest suffix that is a prefix of a stop sequence
+        let keep_from = self
+            .pending
+            .char_indices()
+            .map(|(i, _)| i)
+            .find(|&i| {
+                let tail = &self.pending[i..];
+                self.stops.iter().any(|s| s.starts_with(tail))
+            })
+            .unwrap_or(self.pending.len());
+        let out = self.pending[..keep_from].to_string();
+        self.pending.drain(..keep_from);
+        (out, false)
+    }
+
+    /// Text still held back when generation ended without a stop sequence
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new(Role::System, "You are a risk assistant."),
+            ChatMessage::new(Role::User, "Drawdown?"),
+            ChatMessage::new(Role::Assistant, "3.2%"),
+            ChatMessage::new(Role::User, "Is that high?"),
+        ]
+    }
+
+    #[test]
+    fn test_render_templates() {
+        assert_eq!(
+            PromptTemplate::ChatMl.render(&ChatMessage::single_turn("sys", "hi")),
+            "<|im_start|>system\nsys<|im_end|>\n<|im_start|>user\nhi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            PromptTemplate::Llama2.render(&conversation()),
+            "[INST] <<SYS>>\nYou are a risk assistant.\n<</SYS>>\n\nDrawdown? [/INST] 3.2%</s><s>[INST] Is that high? [/INST]"
+        );
+        assert_eq!(
+            PromptTemplate::Mistral.render(&conversation()),
+            "[INST] You are a risk assistant.\n\nDrawdown? [/INST] 3.2%</s>[INST] Is that high? [/INST]"
+        );
+        assert!(PromptTemplate::Alpaca
+            .render(&conversation())
+            .ends_with("### Response:\n3.2%\n\n### Instruction:\nIs that high?\n\n### Response:\n"));
+
+        let custom: PromptTemplate = serde_json::from_str(
+            r#"{"custom": {"user": "Q: {content}\n", "assistant": "A: {content}\n", "generation_prefix": "A:", "stop": ["Q:"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(custom.render(&conversation()), "You are a risk assistant.\n\nQ: Drawdown?\nA: 3.2%\nQ: Is that high?\nA:");
+        assert_eq!(custom.stop_sequences(), vec!["Q:"]);
+        assert_eq!(serde_json::from_str::<PromptTemplate>("\"llama2\"").unwrap(), PromptTemplate::Llama2);
+    }
+
+    #[test]
+    fn test_stop_matcher_split_marker() {
+        let mut matcher = StopMatcher::new(PromptTemplate::ChatMl.stop_sequences());
+        assert_eq!(matcher.push("Equity is "), ("Equity is ".to_string(), false));
+        assert_eq!(matcher.push("fine.<|im"), ("fine.".to_string(), false));
+        assert_eq!(matcher.push("_end|>\nextra"), (String::new(), true));
+
+        // A partial marker that turns out not to be one is released
+        let mut matcher = StopMatcher::new(vec!["</s>".to_string()]);
+        assert_eq!(matcher.push("a <"), ("a ".to_string(), false));
+        assert_eq!(matcher.push("b"), ("<b".to_string(), false));
+        assert_eq!(matcher.push("</"), (String::new(), false));
+        assert_eq!(matcher.finish(), "</");
+    }
+}
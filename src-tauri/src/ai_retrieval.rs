@@ -0,0 +1,636 @@
+//! Local retrieval over trading docs, MQL source and `.set` files
+//! Files under the configured folders are split into line-ranged chunks and indexed with BM25.
+//! The index lives in the app data dir and is rebuilt incrementally: unchanged files keep their
+//! chunks. Requests that opt in get the best chunks appended to their system context and the
+//! matching citations back in the response.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Runtime};
+
+pub const INDEX_FILE: &str = "retrieval_index.json";
+pub const CONFIG_FILE: &str = "retrieval.json";
+
+const CHUNK_LINES: usize = 40;
+/// Lines repeated at the start of the next chunk so a passage isn't cut in half
+const CHUNK_OVERLAP: usize = 8;
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+const DEFAULT_TOP_K: usize = 4;
+const MAX_TOP_K: usize = 12;
+/// Budget for retrieved text in the prompt, and for any single chunk within it
+const MAX_CONTEXT_CHARS: usize = 6000;
+const MAX_CHUNK_CHARS: usize = 1500;
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "dist", "build"];
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "or", "of", "to", "in", "is", "it", "for", "on", "at", "by", "be", "as", "an", "with", "this",
+    "that", "what", "how", "does", "do", "are", "we", "our", "from",
+];
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalConfig {
+    #[serde(default)]
+    pub folders: Vec<PathBuf>,
+    /// File extensions to index, without the dot
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+}
+
+fn default_extensions() -> Vec<String> {
+    ["md", "mq4", "mq5", "mqh", "set"].iter().map(|e| e.to_string()).collect()
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            folders: Vec::new(),
+            extensions: default_extensions(),
+        }
+    }
+}
+
+/// Opt-in on `AiRequest`: `"retrieval": {"top_k": 4}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetrievalOptions {
+    #[serde(default)]
+    pub top_k: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub path: String,
+    /// 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Nearest Markdown heading above the chunk
+    pub heading: Option<String>,
+    pub text: String,
+    terms: HashMap<String, u32>,
+    len: u32,
+}
+
+/// A retrieved chunk as reported to callers; `index` is the `[n]` the model cites
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Citation {
+    pub index: usize,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub heading: Option<String>,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    modified: u64,
+    size: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedIndex {
+    files: HashMap<String, FileStamp>,
+    chunks: Vec<Chunk>,
+    /// Milliseconds since the Unix epoch
+    built_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatus {
+    pub folders: Vec<PathBuf>,
+    pub extensions: Vec<String>,
+    pub files: usize,
+    pub chunks: usize,
+    pub built_at: Option<i64>,
+    pub rebuilding: bool,
+}
+
+// ============ Chunking ============
+
+/// Splits `MyTakeProfit`, `take_profit` and `TP2` style identifiers into their parts
+fn identifier_parts(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    for piece in word.split('_').filter(|p| !p.is_empty()) {
+        let mut current = String::new();
+        let mut prev_lower = false;
+        for c in piece.chars() {
+            if c.is_uppercase() && prev_lower && !current.is_empty() {
+                parts.push(std::mem::take(&mut current));
+            }
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+            current.push(c);
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+    }
+    parts
+}
+
+/// Lowercased terms of `text`; compound identifiers add their parts as well as the whole word
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|w| !w.is_empty()) {
+        let parts = identifier_parts(word);
+        if parts.len() > 1 {
+            terms.extend(parts.iter().map(|p| p.to_lowercase()));
+        }
+        terms.push(word.to_lowercase());
+    }
+    terms.retain(|t| t.chars().count() > 1 && !STOP_WORDS.contains(&t.as_str()));
+    terms
+}
+
+fn new_chunk(path: &str, lines: &[&str], start: usize, heading: Option<String>) -> Option<Chunk> {
+    let text = lines.join("\n");
+    if text.trim().is_empty() {
+        return None;
+    }
+    let mut terms: HashMap<String, u32> = HashMap::new();
+    let mut len = 0;
+    for term in tokenize(&text).into_iter().chain(heading.iter().flat_map(|h| tokenize(h))) {
+        *terms.entry(term).or_default() += 1;
+        len += 1;
+    }
+    Some(Chunk {
+        path: path.to_string(),
+        start_line: start + 1,
+        end_line: start + lines.len(),
+        heading,
+        text,
+        terms,
+        len,
+    })
+}
+
+/// Chunks of at most `CHUNK_LINES` lines; Markdown additionally starts a chunk at every heading
+pub fn chunk_text(path: &str, content: &str, markdown: bool) -> Vec<Chunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut sections: Vec<(Option<String>, usize, usize)> = Vec::new();
+    let mut section_start = 0;
+    let mut heading = None;
+    for (i, line) in lines.iter().enumerate() {
+        if markdown && line.starts_with('#') {
+            if i > section_start {
+                sections.push((heading.clone(), section_start, i));
+            }
+            heading = Some(line.trim_start_matches('#').trim().to_string());
+            section_start = i;
+        }
+    }
+    sections.push((heading, section_start, lines.len()));
+
+    let mut chunks = Vec::new();
+    for (heading, start, end) in sections {
+        let mut from = start;
+        while from < end {
+            let to = (from + CHUNK_LINES).min(end);
+            chunks.extend(new_chunk(path, &lines[from..to], from, heading.clone()));
+            if to == end {
+                break;
+            }
+            from = to - CHUNK_OVERLAP;
+        }
+    }
+    chunks
+}
+
+/// MetaEditor saves some `.set` and source files as UTF-16
+fn decode(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = rest.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        return String::from_utf16_lossy(&units);
+    }
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn collect_files(dir: &Path, extensions: &[String], files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        // `file_type` doesn't follow symlinks: linked folders (which may loop) are not descended into
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_files(&path, extensions, files);
+            }
+        } else if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e.as_str())))
+        {
+            files.push(path);
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(FileStamp {
+        modified,
+        size: metadata.len(),
+    })
+}
+
+// ============ Index ============
+
+#[derive(Default)]
+struct IndexInner {
+    config: RetrievalConfig,
+    store: PersistedIndex,
+    doc_freq: HashMap<String, u32>,
+    avg_len: f64,
+}
+
+impl IndexInner {
+    fn refresh_stats(&mut self) {
+        self.doc_freq.clear();
+        for chunk in &self.store.chunks {
+            for term in chunk.terms.keys() {
+                *self.doc_freq.entry(term.clone()).or_default() += 1;
+            }
+        }
+        let total: u64 = self.store.chunks.iter().map(|c| c.len as u64).sum();
+        self.avg_len = total as f64 / self.store.chunks.len().max(1) as f64;
+    }
+
+    fn score(&self, terms: &[String], chunk: &Chunk) -> f64 {
+        let n = self.store.chunks.len() as f64;
+        terms
+            .iter()
+            .filter_map(|term| {
+                let tf = *chunk.terms.get(term)? as f64;
+                let df = *self.doc_freq.get(term).unwrap_or(&0) as f64;
+                let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+                let norm = 1.0 - B + B * chunk.len as f64 / self.avg_len.max(1.0);
+                Some(idf * tf * (K1 + 1.0) / (tf + K1 * norm))
+            })
+            .sum()
+    }
+}
+
+/// BM25 index over the configured folders
+pub struct RetrievalIndex {
+    config_path: PathBuf,
+    index_path: PathBuf,
+    inner: RwLock<IndexInner>,
+    rebuilding: AtomicBool,
+}
+
+impl RetrievalIndex {
+    /// Loads the config and the last built index, starting empty if either is missing or invalid
+    pub fn load(config_path: PathBuf, index_path: PathBuf) -> Self {
+        let config = std::fs::read_to_string(&config_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let store = std::fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let mut inner = IndexInner {
+            config,
+            store,
+            ..Default::default()
+        };
+        inner.refresh_stats();
+        Self {
+            config_path,
+            index_path,
+            inner: RwLock::new(inner),
+            rebuilding: AtomicBool::new(false),
+        }
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        let inner = self.inner.read().unwrap();
+        IndexStatus {
+            folders: inner.config.folders.clone(),
+            extensions: inner.config.extensions.clone(),
+            files: inner.store.files.len(),
+            chunks: inner.store.chunks.len(),
+            built_at: inner.store.built_at,
+            rebuilding: self.rebuilding.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn set_config(&self, config: RetrievalConfig) -> Result<(), String> {
+        if let Some(parent) = self.config_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+        std::fs::write(&self.config_path, json).map_err(|e| format!("Failed to write {:?}: {}", self.config_path, e))?;
+        self.inner.write().unwrap().config = config;
+        Ok(())
+    }
+
+    /// Re-reads changed files under the configured folders and saves the index. Blocking.
+    pub fn rebuild(&self) -> Result<IndexStatus, String> {
+        if self.rebuilding.swap(true, Ordering::SeqCst) {
+            return Err("The document index is already being rebuilt".to_string());
+        }
+        let result = self.rebuild_inner();
+        self.rebuilding.store(false, Ordering::SeqCst);
+        result.map(|()| self.status())
+    }
+
+    fn rebuild_inner(&self) -> Result<(), String> {
+        let (config, mut previous) = {
+            let inner = self.inner.read().unwrap();
+            let mut previous: HashMap<String, (FileStamp, Vec<Chunk>)> = HashMap::new();
+            for (path, stamp) in &inner.store.files {
+                previous.insert(path.clone(), (*stamp, Vec::new()));
+            }
+            for chunk in &inner.store.chunks {
+                if let Some((_, chunks)) = previous.get_mut(&chunk.path) {
+                    chunks.push(chunk.clone());
+                }
+            }
+            (inner.config.clone(), previous)
+        };
+
+        let mut files = Vec::new();
+        for folder in &config.folders {
+            collect_files(folder, &config.extensions, &mut files);
+        }
+        files.sort();
+        files.dedup();
+
+        let mut store = PersistedIndex::default();
+        for file in files {
+            let Some(stamp) = stamp(&file) else { continue };
+            if stamp.size > MAX_FILE_BYTES {
+                continue;
+            }
+            let key = file.to_string_lossy().to_string();
+            let chunks = match previous.remove(&key) {
+                Some((old, chunks)) if old == stamp => chunks,
+                _ => {
+                    let Ok(bytes) = std::fs::read(&file) else { continue };
+                    let markdown = file.extension().is_some_and(|e| e.eq_ignore_ascii_case("md"));
+                    chunk_text(&key, &decode(&bytes), markdown)
+                }
+            };
+            store.files.insert(key, stamp);
+            store.chunks.extend(chunks);
+        }
+        store.built_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_millis() as i64);
+
+        if let Some(parent) = self.index_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let json = serde_json::to_string(&store).map_err(|e| e.to_string())?;
+        std::fs::write(&self.index_path, json).map_err(|e| format!("Failed to write {:?}: {}", self.index_path, e))?;
+
+        let mut inner = self.inner.write().unwrap();
+        inner.store = store;
+        inner.refresh_stats();
+        Ok(())
+    }
+
+    /// The `top_k` best chunks for `query`, best first
+    pub fn search(&self, query: &str, top_k: usize) -> Vec<(f64, Chunk)> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        let inner = self.inner.read().unwrap();
+        let mut hits: Vec<(f64, &Chunk)> = inner
+            .store
+            .chunks
+            .iter()
+            .map(|chunk| (inner.score(&terms, chunk), chunk))
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.0.total_cmp(&a.0));
+        hits.into_iter().take(top_k).map(|(score, chunk)| (score, chunk.clone())).collect()
+    }
+}
+
+/// Appends the best chunks for `prompt` to `system_context`, numbered for citation
+pub fn augment_context(
+    index: &RetrievalIndex,
+    system_context: &str,
+    prompt: &str,
+    options: &RetrievalOptions,
+) -> (String, Vec<Citation>) {
+    let top_k = options.top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K);
+    let hits = index.search(prompt, top_k);
+    if hits.is_empty() {
+        return (system_context.to_string(), Vec::new());
+    }
+
+    let mut context = system_context.trim_end().to_string();
+    if !context.is_empty() {
+        context.push_str("\n\n");
+    }
+    context.push_str("Reference material from local files. Cite the sources you use as [n]:\n");
+    let mut citations = Vec::new();
+    let mut used = 0;
+    for (score, chunk) in hits {
+        let text = match chunk.text.char_indices().nth(MAX_CHUNK_CHARS) {
+            Some((cut, _)) => &chunk.text[..cut],
+            None => chunk.text.as_str(),
+        };
+        if used + text.len() > MAX_CONTEXT_CHARS && !citations.is_empty() {
+            break;
+        }
+        used += text.len();
+        let index = citations.len() + 1;
+        context.push_str(&format!(
+            "\n[{}] {} (lines {}-{})\n{}\n",
+            index, chunk.path, chunk.start_line, chunk.end_line, text
+        ));
+        citations.push(Citation {
+            index,
+            path: chunk.path,
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            heading: chunk.heading,
+            score,
+        });
+    }
+    (context, citations)
+}
+
+/// `system_context` with retrieved chunks when the request opted in and an index is loaded
+pub fn retrieve<R: Runtime>(
+    app: &AppHandle<R>,
+    system_context: &str,
+    prompt: &str,
+    options: Option<&RetrievalOptions>,
+) -> (String, Vec<Citation>) {
+    match (options, app.try_state::<Arc<RetrievalIndex>>()) {
+        (Some(options), Some(index)) => augment_context(&index, system_context, prompt, options),
+        _ => (system_context.to_string(), Vec::new()),
+    }
+}
+
+// ============ Commands ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub score: f64,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub heading: Option<String>,
+    pub text: String,
+}
+
+#[tauri::command]
+pub fn retrieval_status(index: tauri::State<'_, Arc<RetrievalIndex>>) -> IndexStatus {
+    index.status()
+}
+
+/// Saves the folders and extensions to index, then rebuilds
+#[tauri::command]
+pub async fn set_retrieval_config(
+    index: tauri::State<'_, Arc<RetrievalIndex>>,
+    config: RetrievalConfig,
+) -> Result<IndexStatus, String> {
+    index.set_config(config)?;
+    let index = index.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || index.rebuild())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn rebuild_retrieval_index(index: tauri::State<'_, Arc<RetrievalIndex>>) -> Result<IndexStatus, String> {
+    let index = index.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || index.rebuild())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn search_documents(
+    index: tauri::State<'_, Arc<RetrievalIndex>>,
+    query: String,
+    top_k: Option<usize>,
+) -> Vec<SearchHit> {
+    let top_k = top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K);
+    index
+        .search(&query, top_k)
+        .into_iter()
+        .map(|(score, chunk)| SearchHit {
+            score,
+            path: chunk.path,
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            heading: chunk.heading,
+            text: chunk.text,
+        })
+        .collect()
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_splits_identifiers() {
+        let terms = tokenize("extern double TakeProfit = 50; // the max_spread filter");
+        for expected in ["takeprofit", "take", "profit", "max_spread", "max", "spread", "filter", "50"] {
+            assert!(terms.contains(&expected.to_string()), "missing {}", expected);
+        }
+        assert!(!terms.contains(&"the".to_string()));
+    }
+
+    #[test]
+    fn test_chunk_text_markdown_sections_and_windows() {
+        let mut doc = String::from("intro\n# Risk\nmax drawdown 20%\n## Lots\n");
+        for i in 0..CHUNK_LINES + 10 {
+            doc.push_str(&format!("line {}\n", i));
+        }
+        let chunks = chunk_text("docs/strategy.md", &doc, true);
+        assert_eq!(chunks[0].heading, None);
+        assert_eq!(chunks[1].heading.as_deref(), Some("Risk"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (2, 3));
+        assert_eq!(chunks[2].heading.as_deref(), Some("Lots"));
+        assert_eq!(chunks[2].end_line - chunks[2].start_line + 1, CHUNK_LINES);
+        assert_eq!(chunks[3].start_line, chunks[2].end_line - CHUNK_OVERLAP + 1);
+        assert_eq!(chunks[3].end_line, doc.lines().count());
+    }
+
+    #[test]
+    fn test_decode_utf16_set_file() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("Lots=0.1".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert_eq!(decode(&bytes), "Lots=0.1");
+        assert_eq!(decode(b"\xEF\xBB\xBFMagic=7"), "Magic=7");
+    }
+
+    #[test]
+    fn test_rebuild_search_and_augment() {
+        let dir = std::env::temp_dir().join("daavfx_retrieval_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(docs.join("node_modules")).unwrap();
+        std::fs::write(docs.join("grid.md"), "# Grid\nThe grid step widens after each loss.\n").unwrap();
+        std::fs::write(docs.join("scalper.set"), "TakeProfit=12\nStopLoss=30\n").unwrap();
+        std::fs::write(docs.join("notes.txt"), "grid grid grid").unwrap();
+        std::fs::write(docs.join("node_modules").join("readme.md"), "grid").unwrap();
+
+        let index = RetrievalIndex::load(dir.join(CONFIG_FILE), dir.join(INDEX_FILE));
+        index
+            .set_config(RetrievalConfig {
+                folders: vec![docs.clone()],
+                ..Default::default()
+            })
+            .unwrap();
+        let status = index.rebuild().unwrap();
+        assert_eq!((status.files, status.chunks), (2, 2));
+
+        let hits = index.search("what is the take profit?", 3);
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].1.path.ends_with("scalper.set"));
+
+        // The saved index is picked up again on load
+        let reloaded = RetrievalIndex::load(dir.join(CONFIG_FILE), dir.join(INDEX_FILE));
+        let (context, citations) =
+            augment_context(&reloaded, "You are a trading assistant.", "how does the grid step work", &Default::default());
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].heading.as_deref(), Some("Grid"));
+        assert!(context.starts_with("You are a trading assistant.\n\n"));
+        assert!(context.contains("[1] ") && context.contains("widens after each loss"));
+
+        let (context, citations) = augment_context(&reloaded, "ctx", "unrelated question", &Default::default());
+        assert_eq!((context.as_str(), citations.len()), ("ctx", 0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_skips_symlinked_dirs() {
+        let dir = std::env::temp_dir().join("daavfx_retrieval_symlink_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("a.md"), "grid").unwrap();
+        // A link back to the root would recurse forever if followed
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+
+        let mut files = Vec::new();
+        collect_files(&dir, &["md".to_string()], &mut files);
+        assert_eq!(files, vec![dir.join("sub").join("a.md")]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
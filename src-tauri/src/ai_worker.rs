@@ -0,0 +1,295 @@
+//! Dedicated inference worker pool
+//! `session.infer` is CPU bound and blocks for seconds, so it runs on its own OS threads fed by a
+//! bounded queue instead of on tokio workers shared with the pulse monitor, log readers and axum.
+
+use serde::Serialize;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_WORKERS: usize = 2;
+pub const DEFAULT_QUEUE_CAPACITY: usize = 16;
+/// Upper bound for one request, including the time spent waiting in the queue
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkerError {
+    /// Queue is full; retry after the given number of seconds
+    Busy { retry_after_secs: u64 },
+    TimedOut,
+    Failed(String),
+    /// The request itself is wrong, e.g. invalid options or an unknown model
+    Invalid(String),
+    /// A conversation or model file the request needs doesn't exist
+    NotFound(String),
+    /// The conversation is already generating a reply
+    Conflict(String),
+}
+
+impl WorkerError {
+    pub fn status(&self) -> axum::http::StatusCode {
+        match self {
+            WorkerError::Busy { .. } => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            WorkerError::TimedOut => axum::http::StatusCode::GATEWAY_TIMEOUT,
+            WorkerError::Failed(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            WorkerError::Invalid(_) => axum::http::StatusCode::BAD_REQUEST,
+            WorkerError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            WorkerError::Conflict(_) => axum::http::StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl std::fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkerError::Busy { retry_after_secs } => {
+                write!(f, "AI server busy, retry in {}s", retry_after_secs)
+            }
+            WorkerError::TimedOut => write!(f, "AI request timed out"),
+            WorkerError::Failed(e)
+            | WorkerError::Invalid(e)
+            | WorkerError::NotFound(e)
+            | WorkerError::Conflict(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<WorkerError> for String {
+    fn from(e: WorkerError) -> Self {
+        e.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueMetrics {
+    pub workers: usize,
+    pub capacity: usize,
+    pub queued: usize,
+    pub running: usize,
+    pub completed: u64,
+    pub rejected: u64,
+    pub timed_out: u64,
+    pub avg_job_ms: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+    busy_ms: AtomicU64,
+}
+
+pub struct WorkerPool {
+    sender: SyncSender<Job>,
+    workers: usize,
+    capacity: usize,
+    counters: Arc<Counters>,
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORKERS, DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = sync_channel::<Job>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("ai-worker-{}", i))
+                .spawn(move || worker_loop(receiver))
+                .expect("failed to spawn AI worker thread");
+        }
+        Self {
+            sender,
+            workers,
+            capacity,
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    /// Queues `job` without blocking. Fails with `Busy` when the queue is full.
+    /// A job whose `cancel` flag is set before a worker picks it up is dropped unrun.
+    pub fn enqueue<T, F>(&self, cancel: Arc<AtomicBool>, job: F) -> Result<JobHandle<T>, WorkerError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let counters = self.counters.clone();
+        let job_cancel = cancel.clone();
+        let wrapped: Job = Box::new(move || {
+            counters.queued.fetch_sub(1, Ordering::SeqCst);
+            if job_cancel.load(Ordering::SeqCst) {
+                return;
+            }
+            counters.running.fetch_add(1, Ordering::SeqCst);
+            let started = Instant::now();
+            // A panicking job must not take the worker thread or the running count with it
+            let result = std::panic::catch_unwind(AssertUnwindSafe(job))
+                .map_err(|_| WorkerError::Failed("inference panicked".to_string()));
+            counters.busy_ms.fetch_add(started.elapsed().as_millis() as u64, Ordering::SeqCst);
+            counters.completed.fetch_add(1, Ordering::SeqCst);
+            counters.running.fetch_sub(1, Ordering::SeqCst);
+            let _ = tx.send(result);
+        });
+
+        self.counters.queued.fetch_add(1, Ordering::SeqCst);
+        match self.sender.try_send(wrapped) {
+            Ok(()) => Ok(JobHandle {
+                receiver: rx,
+                cancel,
+                counters: self.counters.clone(),
+            }),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.counters.queued.fetch_sub(1, Ordering::SeqCst);
+                self.counters.rejected.fetch_add(1, Ordering::SeqCst);
+                Err(WorkerError::Busy {
+                    retry_after_secs: self.retry_after_secs(),
+                })
+            }
+        }
+    }
+
+    /// Queues `job` and waits for its result for at most `timeout`
+    pub async fn run<T, F>(&self, cancel: Arc<AtomicBool>, timeout: Duration, job: F) -> Result<T, WorkerError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.enqueue(cancel, job)?.wait(timeout).await
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        let completed = self.counters.completed.load(Ordering::SeqCst);
+        QueueMetrics {
+            workers: self.workers,
+            capacity: self.capacity,
+            queued: self.counters.queued.load(Ordering::SeqCst),
+            running: self.counters.running.load(Ordering::SeqCst),
+            completed,
+            rejected: self.counters.rejected.load(Ordering::SeqCst),
+            timed_out: self.counters.timed_out.load(Ordering::SeqCst),
+            avg_job_ms: self.counters.busy_ms.load(Ordering::SeqCst).checked_div(completed).unwrap_or(0),
+        }
+    }
+
+    /// Rough time until a queue slot frees up, from the average job duration
+    fn retry_after_secs(&self) -> u64 {
+        let metrics = self.metrics();
+        let backlog = (metrics.queued + metrics.running) as u64;
+        let secs = (metrics.avg_job_ms * backlog / self.workers as u64).div_ceil(1000);
+        secs.clamp(1, 60)
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return, // pool dropped
+        };
+        job();
+    }
+}
+
+pub struct JobHandle<T> {
+    receiver: tokio::sync::oneshot::Receiver<Result<T, WorkerError>>,
+    cancel: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+}
+
+impl<T> JobHandle<T> {
+    /// Waits for the job. On timeout the job's cancel flag is raised so it stops (or never starts).
+    pub async fn wait(self, timeout: Duration) -> Result<T, WorkerError> {
+        match tokio::time::timeout(timeout, self.receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(WorkerError::Failed("AI request was cancelled".to_string())),
+            Err(_) => {
+                self.cancel.store(true, Ordering::SeqCst);
+                self.counters.timed_out.fetch_add(1, Ordering::SeqCst);
+                Err(WorkerError::TimedOut)
+            }
+        }
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_full_and_timeout() {
+        let pool = WorkerPool::new(1, 1);
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+
+        // Occupy the only worker until released
+        let blocker = pool
+            .enqueue(Arc::new(AtomicBool::new(false)), move || {
+                let _ = release_rx.recv();
+                1
+            })
+            .unwrap();
+        while pool.metrics().running == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // One job fits in the queue, the next is rejected
+        let queued_cancel = Arc::new(AtomicBool::new(false));
+        let queued = pool.enqueue(queued_cancel.clone(), || 2).unwrap();
+        let rejected = pool.enqueue(Arc::new(AtomicBool::new(false)), || 3);
+        assert!(matches!(rejected, Err(WorkerError::Busy { .. })));
+        assert_eq!(pool.metrics().queued, 1);
+        assert_eq!(pool.metrics().rejected, 1);
+
+        // The queued job times out and is skipped once the worker frees up
+        assert_eq!(queued.wait(Duration::from_millis(20)).await, Err(WorkerError::TimedOut));
+        assert!(queued_cancel.load(Ordering::SeqCst));
+        release_tx.send(()).unwrap();
+        assert_eq!(blocker.wait(Duration::from_secs(5)).await, Ok(1));
+
+        let result = pool.run(Arc::new(AtomicBool::new(false)), Duration::from_secs(5), || 4).await;
+        assert_eq!(result, Ok(4));
+        let metrics = pool.metrics();
+        assert_eq!(metrics.completed, 2);
+        assert_eq!(metrics.timed_out, 1);
+        assert_eq!(metrics.queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_job() {
+        let pool = WorkerPool::new(1, 1);
+        let result = pool
+            .run(Arc::new(AtomicBool::new(false)), Duration::from_secs(5), || -> i32 { panic!("model crashed") })
+            .await;
+        assert_eq!(result, Err(WorkerError::Failed("inference panicked".to_string())));
+        assert_eq!(pool.metrics().running, 0);
+
+        // The worker thread survived
+        let result = pool.run(Arc::new(AtomicBool::new(false)), Duration::from_secs(5), || 5).await;
+        assert_eq!(result, Ok(5));
+    }
+
+    #[test]
+    fn test_error_status() {
+        use axum::http::StatusCode;
+        assert_eq!(WorkerError::Busy { retry_after_secs: 1 }.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(WorkerError::Failed("x".into()).status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(WorkerError::Invalid("x".into()).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(WorkerError::NotFound("x".into()).status(), StatusCode::NOT_FOUND);
+        assert_eq!(WorkerError::Conflict("x".into()).status(), StatusCode::CONFLICT);
+    }
+}
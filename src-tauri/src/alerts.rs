@@ -0,0 +1,490 @@
+//! Account risk alerts
+//! Evaluates user-defined rules against the pulse feed with hysteresis and cooldown
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_notification::NotificationExt;
+
+/// Maximum number of alerts kept in the history
+const MAX_HISTORY: usize = 500;
+
+/// What a rule watches for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Drawdown (in percent) at or above the threshold
+    DrawdownAbove { percent: f64 },
+    /// Equity fell by `amount` from its peak within the last `window_minutes`
+    EquityDrop { amount: f64, window_minutes: u64 },
+    /// No new pulse for `seconds` - the EA or terminal has most likely died
+    StalePulse { seconds: u64 },
+    /// Margin level (in percent) at or below the threshold
+    MarginLevelBelow { percent: f64 },
+}
+
+/// User-defined alert rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    /// Account the rule applies to; None applies it to every account
+    #[serde(default)]
+    pub account_id: Option<String>,
+    pub condition: AlertCondition,
+    /// Distance the value must move back past the threshold before the rule re-arms
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Minimum time between two alerts of the same rule
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A fired alert, emitted as `risk-alert` and kept in the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub id: u64,
+    pub rule_id: String,
+    pub rule_name: String,
+    #[serde(default)]
+    pub account_id: String,
+    pub message: String,
+    pub value: f64,
+    pub threshold: f64,
+    pub triggered_at: i64, // Unix timestamp
+    pub acknowledged: bool,
+}
+
+/// Numeric view of a single pulse line
+#[derive(Debug, Clone, Copy)]
+pub struct PulseReading {
+    pub balance: f64,
+    pub equity: f64,
+    pub drawdown: f64,
+    pub margin_level: Option<f64>,
+}
+
+impl PulseReading {
+    /// Parses `balance,equity,drawdown,timestamp[,margin_level]`
+    pub fn parse(parts: &[&str]) -> Option<Self> {
+        if parts.len() < 4 {
+            return None;
+        }
+        Some(Self {
+            balance: parts[0].trim().parse().ok()?,
+            equity: parts[1].trim().parse().ok()?,
+            drawdown: parts[2].trim().parse().ok()?,
+            margin_level: parts.get(4).and_then(|m| m.trim().parse().ok()),
+        })
+    }
+}
+
+#[derive(Default)]
+struct RuleState {
+    active: bool,
+    last_fired: Option<Instant>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedAlerts {
+    rules: Vec<AlertRule>,
+    history: VecDeque<AlertEvent>,
+    next_id: u64,
+}
+
+#[derive(Default)]
+struct AccountState {
+    last_reading: Option<PulseReading>,
+    equity_samples: VecDeque<(Instant, f64)>,
+}
+
+struct EngineInner {
+    store: PersistedAlerts,
+    /// Keyed by (rule id, account id)
+    rule_states: HashMap<(String, String), RuleState>,
+    accounts: HashMap<String, AccountState>,
+}
+
+/// Alert engine shared between the pulse monitor and the Tauri commands
+pub struct AlertEngine {
+    path: PathBuf,
+    inner: Mutex<EngineInner>,
+}
+
+impl AlertEngine {
+    /// Loads rules and history from `path`, starting empty if the file is missing or invalid
+    pub fn load(path: PathBuf) -> Self {
+        let store = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            inner: Mutex::new(EngineInner {
+                store,
+                rule_states: HashMap::new(),
+                accounts: HashMap::new(),
+            }),
+        }
+    }
+
+    fn save(&self, store: &PersistedAlerts) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let json = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json).map_err(|e| format!("Failed to write {:?}: {}", self.path, e))
+    }
+
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.inner.lock().unwrap().store.rules.clone()
+    }
+
+    /// Inserts or replaces the rule with the same id
+    pub fn upsert_rule(&self, rule: AlertRule) -> Result<(), String> {
+        if rule.id.trim().is_empty() {
+            return Err("Alert rule id must not be empty".to_string());
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.rule_states.retain(|(rule_id, _), _| *rule_id != rule.id);
+        match inner.store.rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => *existing = rule,
+            None => inner.store.rules.push(rule),
+        }
+        self.save(&inner.store)
+    }
+
+    pub fn remove_rule(&self, rule_id: &str) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.store.rules.len();
+        inner.store.rules.retain(|r| r.id != rule_id);
+        if inner.store.rules.len() == before {
+            return Err(format!("Alert rule '{}' not found", rule_id));
+        }
+        inner.rule_states.retain(|(id, _), _| id != rule_id);
+        self.save(&inner.store)
+    }
+
+    /// Returns the alert history, newest first
+    pub fn history(&self) -> Vec<AlertEvent> {
+        self.inner.lock().unwrap().store.history.iter().rev().cloned().collect()
+    }
+
+    pub fn acknowledge(&self, alert_id: u64) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let alert = inner
+            .store
+            .history
+            .iter_mut()
+            .find(|a| a.id == alert_id)
+            .ok_or_else(|| format!("Alert {} not found", alert_id))?;
+        alert.acknowledged = true;
+        self.save(&inner.store)
+    }
+
+    pub fn acknowledge_all(&self) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        for alert in inner.store.history.iter_mut() {
+            alert.acknowledged = true;
+        }
+        self.save(&inner.store)
+    }
+
+    /// Evaluates every enabled rule that applies to `account_id`.
+    /// `reading` is the newly accepted pulse (if any) and `pulse_age` the time since the last one.
+    /// Returns the alerts that fired on this pass.
+    pub fn evaluate(&self, account_id: &str, reading: Option<PulseReading>, pulse_age: Duration) -> Vec<AlertEvent> {
+        let now = Instant::now();
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let account = inner.accounts.entry(account_id.to_string()).or_default();
+
+        if let Some(reading) = reading {
+            account.last_reading = Some(reading);
+            account.equity_samples.push_back((now, reading.equity));
+        }
+
+        // Keep only as much equity history as the widest window needs
+        let max_window = inner
+            .store
+            .rules
+            .iter()
+            .filter_map(|r| match r.condition {
+                AlertCondition::EquityDrop { window_minutes, .. } => Some(Duration::from_secs(window_minutes * 60)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        while let Some((t, _)) = account.equity_samples.front() {
+            if now.duration_since(*t) > max_window {
+                account.equity_samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let mut fired = Vec::new();
+        let applicable = inner
+            .store
+            .rules
+            .iter()
+            .filter(|r| r.enabled && r.account_id.as_deref().is_none_or(|id| id == account_id));
+
+        for rule in applicable {
+            let Some((value, threshold, above, message)) = measure(account, rule, pulse_age, now) else {
+                continue;
+            };
+
+            let breached = if above { value >= threshold } else { value <= threshold };
+            let rearmed = if above {
+                value < threshold - rule.hysteresis
+            } else {
+                value > threshold + rule.hysteresis
+            };
+
+            let state = inner
+                .rule_states
+                .entry((rule.id.clone(), account_id.to_string()))
+                .or_default();
+            if state.active {
+                if rearmed {
+                    state.active = false;
+                }
+                continue;
+            }
+            if !breached {
+                continue;
+            }
+            let cooling_down = state
+                .last_fired
+                .map(|t| now.duration_since(t) < Duration::from_secs(rule.cooldown_secs))
+                .unwrap_or(false);
+            if cooling_down {
+                continue;
+            }
+
+            state.active = true;
+            state.last_fired = Some(now);
+
+            let id = inner.store.next_id;
+            inner.store.next_id += 1;
+            fired.push(AlertEvent {
+                id,
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                account_id: account_id.to_string(),
+                message,
+                value,
+                threshold,
+                triggered_at: unix_now(),
+                acknowledged: false,
+            });
+        }
+
+        if !fired.is_empty() {
+            inner.store.history.extend(fired.iter().cloned());
+            while inner.store.history.len() > MAX_HISTORY {
+                inner.store.history.pop_front();
+            }
+            if let Err(e) = self.save(&inner.store) {
+                println!("[Alerts] {}", e);
+            }
+        }
+
+        fired
+    }
+}
+
+/// Returns `(value, threshold, fires_above, message)` for a rule, or None if there is nothing to measure yet
+fn measure(account: &AccountState, rule: &AlertRule, pulse_age: Duration, now: Instant) -> Option<(f64, f64, bool, String)> {
+    match rule.condition {
+        AlertCondition::DrawdownAbove { percent } => {
+            let dd = account.last_reading?.drawdown;
+            Some((dd, percent, true, format!("Drawdown {:.2}% reached the {:.2}% limit", dd, percent)))
+        }
+        AlertCondition::EquityDrop { amount, window_minutes } => {
+            let current = account.last_reading?.equity;
+            let window = Duration::from_secs(window_minutes * 60);
+            let peak = account
+                .equity_samples
+                .iter()
+                .filter(|(t, _)| now.duration_since(*t) <= window)
+                .map(|(_, e)| *e)
+                .fold(current, f64::max);
+            let drop = peak - current;
+            Some((
+                drop,
+                amount,
+                true,
+                format!("Equity dropped {:.2} within {} minutes (limit {:.2})", drop, window_minutes, amount),
+            ))
+        }
+        AlertCondition::StalePulse { seconds } => {
+            let age = pulse_age.as_secs_f64();
+            Some((
+                age,
+                seconds as f64,
+                true,
+                format!("No pulse for {:.0}s - EA or terminal may have stopped", age),
+            ))
+        }
+        AlertCondition::MarginLevelBelow { percent } => {
+            let margin = account.last_reading?.margin_level?;
+            Some((margin, percent, false, format!("Margin level {:.2}% fell below {:.2}%", margin, percent)))
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Emits a fired alert to the UI and shows a native desktop notification
+pub fn dispatch<R: Runtime>(app: &AppHandle<R>, alert: &AlertEvent) {
+    crate::event_bus::emit(app, "alerts", "risk-alert", alert);
+    let _ = app
+        .notification()
+        .builder()
+        .title(format!("Risk alert [{}]: {}", alert.account_id, alert.rule_name))
+        .body(&alert.message)
+        .show();
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub fn list_alert_rules(engine: tauri::State<'_, AlertEngine>) -> Vec<AlertRule> {
+    engine.rules()
+}
+
+#[tauri::command]
+pub fn save_alert_rule(rule: AlertRule, engine: tauri::State<'_, AlertEngine>) -> Result<(), String> {
+    engine.upsert_rule(rule)
+}
+
+#[tauri::command]
+pub fn delete_alert_rule(rule_id: String, engine: tauri::State<'_, AlertEngine>) -> Result<(), String> {
+    engine.remove_rule(&rule_id)
+}
+
+#[tauri::command]
+pub fn get_alert_history(engine: tauri::State<'_, AlertEngine>) -> Vec<AlertEvent> {
+    engine.history()
+}
+
+#[tauri::command]
+pub fn acknowledge_alert(alert_id: u64, engine: tauri::State<'_, AlertEngine>) -> Result<(), String> {
+    engine.acknowledge(alert_id)
+}
+
+#[tauri::command]
+pub fn acknowledge_all_alerts(engine: tauri::State<'_, AlertEngine>) -> Result<(), String> {
+    engine.acknowledge_all()
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine_with(rule: AlertRule) -> AlertEngine {
+        let path = std::env::temp_dir().join(format!("daavfx_alerts_test_{}.json", rule.id));
+        let _ = std::fs::remove_file(&path);
+        let engine = AlertEngine::load(path);
+        engine.upsert_rule(rule).unwrap();
+        engine
+    }
+
+    fn reading(equity: f64, drawdown: f64) -> Option<PulseReading> {
+        Some(PulseReading { balance: 10_000.0, equity, drawdown, margin_level: None })
+    }
+
+    #[test]
+    fn test_drawdown_hysteresis() {
+        let engine = engine_with(AlertRule {
+            id: "dd_hysteresis".into(),
+            name: "DD".into(),
+            account_id: None,
+            condition: AlertCondition::DrawdownAbove { percent: 10.0 },
+            hysteresis: 2.0,
+            cooldown_secs: 0,
+            enabled: true,
+        });
+        let age = Duration::from_secs(1);
+
+        assert_eq!(engine.evaluate("demo", reading(9_000.0, 11.0), age).len(), 1);
+        // Still breached - no repeat
+        assert!(engine.evaluate("demo", reading(9_000.0, 12.0), age).is_empty());
+        // Inside the hysteresis band - not re-armed yet
+        assert!(engine.evaluate("demo", reading(9_000.0, 9.0), age).is_empty());
+        assert!(engine.evaluate("demo", reading(9_000.0, 10.5), age).is_empty());
+        // Re-armed below 8%, then fires again
+        assert!(engine.evaluate("demo", reading(9_000.0, 7.0), age).is_empty());
+        assert_eq!(engine.evaluate("demo", reading(9_000.0, 10.0), age).len(), 1);
+        assert_eq!(engine.history().len(), 2);
+    }
+
+    #[test]
+    fn test_cooldown_and_acknowledge() {
+        let engine = engine_with(AlertRule {
+            id: "stale_cooldown".into(),
+            name: "Stale".into(),
+            account_id: Some("demo".into()),
+            condition: AlertCondition::StalePulse { seconds: 30 },
+            hysteresis: 0.0,
+            cooldown_secs: 3600,
+            enabled: true,
+        });
+
+        assert_eq!(engine.evaluate("demo", None, Duration::from_secs(45)).len(), 1);
+        assert!(engine.evaluate("demo", None, Duration::from_secs(1)).is_empty());
+        // Re-armed but still cooling down
+        assert!(engine.evaluate("demo", None, Duration::from_secs(45)).is_empty());
+
+        // Rule is scoped to "demo" only
+        assert!(engine.evaluate("live", None, Duration::from_secs(45)).is_empty());
+
+        let alert = engine.history()[0].clone();
+        assert!(!alert.acknowledged);
+        engine.acknowledge(alert.id).unwrap();
+        assert!(engine.history()[0].acknowledged);
+    }
+
+    #[test]
+    fn test_equity_drop_window() {
+        let engine = engine_with(AlertRule {
+            id: "equity_drop".into(),
+            name: "Equity drop".into(),
+            account_id: None,
+            condition: AlertCondition::EquityDrop { amount: 500.0, window_minutes: 15 },
+            hysteresis: 0.0,
+            cooldown_secs: 0,
+            enabled: true,
+        });
+        let age = Duration::from_secs(1);
+
+        assert!(engine.evaluate("demo", reading(10_000.0, 0.0), age).is_empty());
+        assert!(engine.evaluate("demo", reading(9_700.0, 3.0), age).is_empty());
+        let fired = engine.evaluate("demo", reading(9_400.0, 6.0), age);
+        assert_eq!(fired.len(), 1);
+        assert!((fired[0].value - 600.0).abs() < 1e-9);
+    }
+}
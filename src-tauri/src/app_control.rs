@@ -0,0 +1,344 @@
+//! App control over HTTP
+//! Sibling apps can launch, stop and list apps and read their logs through the launcher's server.
+//! The endpoints share `ProcessRegistry` with the `launch_app`/`kill_app` commands.
+
+use crate::{LogPayload, ProcessRegistry};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Lines kept per app for `GET /apps/{id}/logs`
+const LOG_CAPACITY: usize = 1000;
+const DEFAULT_LOG_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlError {
+    UnknownApp(String),
+    NotRunning(String),
+    Failed(String),
+}
+
+impl ControlError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ControlError::UnknownApp(_) => "unknown_app",
+            ControlError::NotRunning(_) => "not_running",
+            ControlError::Failed(_) => "failed",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ControlError::UnknownApp(_) => StatusCode::NOT_FOUND,
+            ControlError::NotRunning(_) => StatusCode::CONFLICT,
+            ControlError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for ControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlError::UnknownApp(id) => write!(f, "Unknown app ID: {}", id),
+            ControlError::NotRunning(id) => write!(f, "App '{}' is not running", id),
+            ControlError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<ControlError> for String {
+    fn from(e: ControlError) -> Self {
+        e.to_string()
+    }
+}
+
+impl From<String> for ControlError {
+    fn from(message: String) -> Self {
+        ControlError::Failed(message)
+    }
+}
+
+/// Body of every error response: `{"error": {"code": "not_running", "message": "..."}}`
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorDetail {
+    code: &'static str,
+    message: String,
+}
+
+impl IntoResponse for ControlError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+// ============ Logs ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    /// Increases across all apps; pass the last one seen as `since`
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: i64,
+    #[serde(rename = "type")]
+    pub log_type: String,
+    pub message: String,
+}
+
+/// Recent `app-log` lines of every app
+#[derive(Default)]
+pub struct LogBuffer {
+    inner: Mutex<LogBufferInner>,
+}
+
+#[derive(Default)]
+struct LogBufferInner {
+    next_seq: u64,
+    apps: HashMap<String, VecDeque<LogLine>>,
+}
+
+impl LogBuffer {
+    pub fn push(&self, app_id: &str, log_type: &str, message: &str, timestamp: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_seq += 1;
+        let seq = inner.next_seq;
+        let lines = inner.apps.entry(app_id.to_string()).or_default();
+        if lines.len() >= LOG_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(LogLine {
+            seq,
+            timestamp,
+            log_type: log_type.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Lines newer than `since`, oldest first, at most `limit` of them
+    pub fn since(&self, app_id: &str, since: u64, limit: usize) -> Vec<LogLine> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .apps
+            .get(app_id)
+            .map(|lines| lines.iter().filter(|l| l.seq > since).take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The newest `count` lines, oldest first
+    pub fn tail(&self, app_id: &str, count: usize) -> Vec<LogLine> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .apps
+            .get(app_id)
+            .map(|lines| lines.iter().skip(lines.len().saturating_sub(count)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn has_app(&self, app_id: &str) -> bool {
+        self.inner.lock().unwrap().apps.contains_key(app_id)
+    }
+}
+
+/// Emits an `app-log` event and keeps the line for `GET /apps/{id}/logs`
+pub fn record_log<R: Runtime>(app: &AppHandle<R>, payload: LogPayload) {
+    crate::metrics::global().inc(
+        crate::metrics::APP_LOG_LINES,
+        &[("app", &payload.app_id), ("type", &payload.log_type)],
+    );
+    if let Some(buffer) = app.try_state::<LogBuffer>() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        buffer.push(&payload.app_id, &payload.log_type, &payload.message, timestamp);
+    }
+    let topic = format!("logs:{}", payload.app_id);
+    crate::event_bus::emit(app, &topic, "app-log", payload);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppStatePayload {
+    pub app_id: String,
+    pub running: bool,
+}
+
+/// Emits `app-state` when the launcher starts or stops an app
+pub fn emit_app_state<R: Runtime>(app: &AppHandle<R>, app_id: &str, running: bool) {
+    let payload = AppStatePayload {
+        app_id: app_id.to_string(),
+        running,
+    };
+    crate::event_bus::emit(app, &format!("apps:{}", app_id), "app-state", payload);
+}
+
+// ============ Apps ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppInfo {
+    pub id: String,
+    /// Reserved Vite dev server port
+    pub port: Option<u16>,
+    pub running: bool,
+    pub pid: Option<u32>,
+}
+
+async fn app_info(app_id: &str, registry: &ProcessRegistry) -> AppInfo {
+    let child = registry.children.get(app_id).map(|entry| entry.value().clone());
+    let (running, pid) = match child {
+        Some(child) => {
+            let mut child = child.lock().await;
+            (matches!(child.try_wait(), Ok(None)), child.id())
+        }
+        None => (false, None),
+    };
+    AppInfo {
+        id: app_id.to_string(),
+        port: crate::get_app_port(app_id),
+        running,
+        pid,
+    }
+}
+
+/// Every launchable app, then any other tracked process (e.g. terminals)
+pub async fn list_apps(registry: &ProcessRegistry) -> Vec<AppInfo> {
+    let mut ids: Vec<String> = crate::APP_IDS
+        .iter()
+        .filter(|id| crate::get_app_dir(id).is_some())
+        .map(|id| id.to_string())
+        .collect();
+    let mut tracked: Vec<String> = registry
+        .children
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|id| !ids.contains(id))
+        .collect();
+    tracked.sort();
+    ids.extend(tracked);
+
+    let mut apps = Vec::with_capacity(ids.len());
+    for id in ids {
+        apps.push(app_info(&id, registry).await);
+    }
+    apps
+}
+
+// ============ HTTP ============
+
+type ServerState = State<Arc<(AppHandle, Arc<crate::ai::AIState>)>>;
+
+/// GET /apps
+pub async fn apps_http_handler(State(state): ServerState) -> Json<Vec<AppInfo>> {
+    let registry = state.0.state::<ProcessRegistry>();
+    Json(list_apps(&registry).await)
+}
+
+/// POST /apps/{id}/launch
+pub async fn launch_app_http_handler(State(state): ServerState, Path(app_id): Path<String>) -> Response {
+    let app = &state.0;
+    let registry = app.state::<ProcessRegistry>();
+    match crate::start_app(&app_id, app, &registry).await {
+        Ok(()) => Json(app_info(&app_id, &registry).await).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// POST /apps/{id}/stop. Only apps the launcher starts itself; MT4/MT5 terminals are not stoppable remotely.
+pub async fn stop_app_http_handler(State(state): ServerState, Path(app_id): Path<String>) -> Response {
+    if crate::get_app_dir(&app_id).is_none() {
+        return ControlError::UnknownApp(app_id).into_response();
+    }
+    let app = &state.0;
+    let registry = app.state::<ProcessRegistry>();
+    match crate::stop_app(&app_id, app, &registry).await {
+        Ok(()) => Json(app_info(&app_id, &registry).await).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    #[serde(default)]
+    pub since: u64,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogPage {
+    pub lines: Vec<LogLine>,
+    /// `since` for the next poll
+    pub next: u64,
+}
+
+/// GET /apps/{id}/logs?since=
+pub async fn app_logs_http_handler(
+    State(state): ServerState,
+    Path(app_id): Path<String>,
+    Query(query): Query<LogQuery>,
+) -> Response {
+    let app = &state.0;
+    let buffer = app.state::<LogBuffer>();
+    let known = crate::get_app_dir(&app_id).is_some()
+        || buffer.has_app(&app_id)
+        || app.state::<ProcessRegistry>().children.contains_key(&app_id);
+    if !known {
+        return ControlError::UnknownApp(app_id).into_response();
+    }
+
+    let lines = buffer.since(&app_id, query.since, query.limit.unwrap_or(DEFAULT_LOG_LIMIT));
+    let next = lines.last().map(|l| l.seq).unwrap_or(query.since);
+    Json(LogPage { lines, next }).into_response()
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_buffer_since_and_capacity() {
+        let buffer = LogBuffer::default();
+        buffer.push("dashboard", "info", "vite ready", 1);
+        buffer.push("copytrader", "error", "boom", 2);
+        buffer.push("dashboard", "info", "hmr update", 3);
+
+        let lines = buffer.since("dashboard", 0, 10);
+        assert_eq!(lines.iter().map(|l| l.seq).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(buffer.since("dashboard", 1, 10)[0].message, "hmr update");
+        assert_eq!(buffer.since("dashboard", 0, 1).len(), 1);
+        assert!(buffer.since("unknown", 0, 10).is_empty());
+        assert_eq!(buffer.tail("dashboard", 1)[0].message, "hmr update");
+
+        for i in 0..LOG_CAPACITY + 5 {
+            buffer.push("copytrader", "info", &i.to_string(), 4);
+        }
+        let lines = buffer.since("copytrader", 0, usize::MAX);
+        assert_eq!(lines.len(), LOG_CAPACITY);
+        assert_eq!(lines.last().unwrap().message, (LOG_CAPACITY + 4).to_string());
+    }
+
+    #[test]
+    fn test_control_error_mapping() {
+        let e = ControlError::NotRunning("dashboard".to_string());
+        assert_eq!(e.status(), StatusCode::CONFLICT);
+        assert_eq!(String::from(e), "App 'dashboard' is not running");
+        assert_eq!(ControlError::UnknownApp("x".to_string()).status(), StatusCode::NOT_FOUND);
+        assert_eq!(ControlError::from("spawn failed".to_string()).code(), "failed");
+    }
+}
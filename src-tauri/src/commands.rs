@@ -442,11 +442,19 @@ pub async fn start_monte_carlo_optimization(
         "elapsed_ms": elapsed
     }));
     
-    Ok(OptimizationOutput {
+    let output = OptimizationOutput {
         results,
         total_combinations,
         elapsed_ms: elapsed,
-    })
+    };
+    
+    // Keep the run for the AI agent's get_optimizer_results tool
+    let results_dir = crate::ai_agent::optimizer_results_dir(&app_handle);
+    if let Err(e) = crate::ai_agent::save_optimizer_run(&results_dir, &output) {
+        println!("[Optimizer] Failed to save results: {}", e);
+    }
+    
+    Ok(output)
 }
 
 #[derive(Debug, Deserialize)]
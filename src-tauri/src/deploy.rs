@@ -0,0 +1,350 @@
+//! Expert Advisor deployment
+//! Copies compiled `.ex4/.ex5` and `.set` files into terminal data folders,
+//! skipping unchanged files, backing up overwritten ones and logging every deployment for rollback
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Runtime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeployAction {
+    Created,
+    Updated,
+    Skipped,
+}
+
+/// One file written (or skipped) by a deployment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployEntry {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub action: DeployAction,
+    pub sha256: String,
+    /// Copy of the file that was overwritten, restored on rollback
+    pub backup: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    pub id: u64,
+    pub timestamp: i64, // Unix timestamp
+    pub source_dir: PathBuf,
+    pub entries: Vec<DeployEntry>,
+    pub rolled_back: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeployRequest {
+    /// Build output folder; relative paths resolve against the ecosystem APPS root
+    pub source_dir: PathBuf,
+    /// Terminal data folders (as reported by `detect_terminals`)
+    pub targets: Vec<PathBuf>,
+    /// Only deploy these file names (all EA and set files when omitted)
+    #[serde(default)]
+    pub files: Option<Vec<String>>,
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// Destination of `file` inside a terminal data folder, or None if it doesn't belong to this terminal
+fn target_path(data_path: &Path, file: &Path) -> Option<PathBuf> {
+    let ext = file.extension()?.to_string_lossy().to_lowercase();
+    let name = file.file_name()?;
+    let is_mt5 = data_path.join("MQL5").is_dir();
+    let is_mt4 = data_path.join("MQL4").is_dir();
+    match ext.as_str() {
+        "ex4" if is_mt4 => Some(data_path.join("MQL4").join("Experts").join(name)),
+        "ex5" if is_mt5 => Some(data_path.join("MQL5").join("Experts").join(name)),
+        "set" if is_mt5 => Some(data_path.join("MQL5").join("Presets").join(name)),
+        "set" if is_mt4 => Some(data_path.join("MQL4").join("Presets").join(name)),
+        _ => None,
+    }
+}
+
+/// Deployment history, stored under `deployments/` in the app data dir
+pub struct DeploymentLog {
+    dir: PathBuf,
+    deployments: Mutex<Vec<Deployment>>,
+}
+
+impl DeploymentLog {
+    pub fn load(dir: PathBuf) -> Self {
+        let deployments = std::fs::read_to_string(dir.join("log.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            dir,
+            deployments: Mutex::new(deployments),
+        }
+    }
+
+    fn save(&self, deployments: &[Deployment]) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {:?}: {}", self.dir, e))?;
+        let json = serde_json::to_string_pretty(deployments).map_err(|e| e.to_string())?;
+        let path = self.dir.join("log.json");
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    pub fn list(&self) -> Vec<Deployment> {
+        self.deployments.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    pub fn deploy(&self, source_dir: &Path, targets: &[PathBuf], only: Option<&[String]>) -> Result<Deployment, String> {
+        let sources: Vec<PathBuf> = std::fs::read_dir(source_dir)
+            .map_err(|e| format!("Failed to read {:?}: {}", source_dir, e))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .filter(|p| {
+                let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                only.is_none_or(|names| names.iter().any(|n| n.eq_ignore_ascii_case(&name)))
+            })
+            .filter(|p| {
+                let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+                matches!(ext.as_str(), "ex4" | "ex5" | "set")
+            })
+            .collect();
+
+        if sources.is_empty() {
+            return Err(format!("No .ex4, .ex5 or .set files to deploy in {:?}", source_dir));
+        }
+        for target in targets {
+            if !target.join("MQL4").is_dir() && !target.join("MQL5").is_dir() {
+                return Err(format!("{:?} is not a terminal data folder (no MQL4/MQL5 directory)", target));
+            }
+        }
+
+        let mut deployments = self.deployments.lock().unwrap();
+        let id = deployments.last().map(|d| d.id + 1).unwrap_or(1);
+        let backup_dir = self.dir.join(id.to_string());
+        let mut entries = Vec::new();
+
+        for target_dir in targets {
+            for source in &sources {
+                let Some(target) = target_path(target_dir, source) else {
+                    continue;
+                };
+                if let Err(e) = deploy_file(source, target, &backup_dir, &mut entries) {
+                    // Put back what was already copied so a failed deployment leaves no trace
+                    for entry in entries.iter().rev() {
+                        let _ = undo_entry(entry);
+                    }
+                    let _ = std::fs::remove_dir_all(&backup_dir);
+                    return Err(e);
+                }
+            }
+        }
+
+        let deployment = Deployment {
+            id,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+            source_dir: source_dir.to_path_buf(),
+            entries,
+            rolled_back: false,
+        };
+        deployments.push(deployment.clone());
+        self.save(&deployments)?;
+        Ok(deployment)
+    }
+
+    /// Restores backed up files and removes files the deployment created.
+    /// Later deployments that changed the same files have to be rolled back first.
+    pub fn rollback(&self, id: u64) -> Result<Deployment, String> {
+        let mut deployments = self.deployments.lock().unwrap();
+        if let Some(index) = deployments.iter().position(|d| d.id == id) {
+            let changed = |d: &Deployment| -> Vec<PathBuf> {
+                d.entries
+                    .iter()
+                    .filter(|e| e.action != DeployAction::Skipped)
+                    .map(|e| e.target.clone())
+                    .collect()
+            };
+            let targets = changed(&deployments[index]);
+            for later in deployments[index + 1..].iter().filter(|d| !d.rolled_back) {
+                if let Some(target) = changed(later).into_iter().find(|t| targets.contains(t)) {
+                    return Err(format!(
+                        "Deployment {} also changed {:?}; roll it back first",
+                        later.id, target
+                    ));
+                }
+            }
+        }
+        let deployment = deployments
+            .iter_mut()
+            .find(|d| d.id == id)
+            .ok_or_else(|| format!("Deployment {} not found", id))?;
+        if deployment.rolled_back {
+            return Err(format!("Deployment {} was already rolled back", id));
+        }
+
+        for entry in deployment.entries.iter().rev() {
+            undo_entry(entry)?;
+        }
+
+        deployment.rolled_back = true;
+        let deployment = deployment.clone();
+        self.save(&deployments)?;
+        Ok(deployment)
+    }
+}
+
+/// Copies one file, backing up the file it replaces. The entry is recorded before the copy
+/// so that a copy failing halfway is undone along with the rest
+fn deploy_file(source: &Path, target: PathBuf, backup_dir: &Path, entries: &mut Vec<DeployEntry>) -> Result<(), String> {
+    let sha256 = sha256_file(source)?;
+
+    let (action, backup) = if !target.exists() {
+        (DeployAction::Created, None)
+    } else if sha256_file(&target)? == sha256 {
+        (DeployAction::Skipped, None)
+    } else {
+        std::fs::create_dir_all(backup_dir)
+            .map_err(|e| format!("Failed to create {:?}: {}", backup_dir, e))?;
+        let backup = backup_dir.join(format!("{}_{}", entries.len(), source.file_name().unwrap().to_string_lossy()));
+        std::fs::copy(&target, &backup).map_err(|e| format!("Failed to back up {:?}: {}", target, e))?;
+        (DeployAction::Updated, Some(backup))
+    };
+
+    entries.push(DeployEntry {
+        source: source.to_path_buf(),
+        target: target.clone(),
+        action,
+        sha256,
+        backup,
+    });
+
+    if action != DeployAction::Skipped {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        std::fs::copy(source, &target).map_err(|e| format!("Failed to copy to {:?}: {}", target, e))?;
+    }
+    Ok(())
+}
+
+/// Reverts one entry: restores the backup or removes the file the deployment created
+fn undo_entry(entry: &DeployEntry) -> Result<(), String> {
+    match (entry.action, &entry.backup) {
+        (DeployAction::Created, _) if entry.target.exists() => {
+            std::fs::remove_file(&entry.target)
+                .map_err(|e| format!("Failed to remove {:?}: {}", entry.target, e))
+        }
+        (DeployAction::Updated, Some(backup)) => std::fs::copy(backup, &entry.target)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to restore {:?}: {}", entry.target, e)),
+        _ => Ok(()),
+    }
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub fn deploy_ea<R: Runtime>(
+    request: DeployRequest,
+    app_handle: AppHandle<R>,
+    log: tauri::State<'_, DeploymentLog>,
+) -> Result<Deployment, String> {
+    if request.targets.is_empty() {
+        return Err("No target terminals selected".to_string());
+    }
+    let source_dir = if request.source_dir.is_relative() {
+        crate::get_apps_base_path(&app_handle)?.join(&request.source_dir)
+    } else {
+        request.source_dir.clone()
+    };
+    log.deploy(&source_dir, &request.targets, request.files.as_deref())
+}
+
+#[tauri::command]
+pub fn list_deployments(log: tauri::State<'_, DeploymentLog>) -> Vec<Deployment> {
+    log.list()
+}
+
+#[tauri::command]
+pub fn rollback_deployment(deployment_id: u64, log: tauri::State<'_, DeploymentLog>) -> Result<Deployment, String> {
+    log.rollback(deployment_id)
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deploy_skip_and_rollback() {
+        let root = std::env::temp_dir().join("daavfx_deploy_test");
+        let _ = std::fs::remove_dir_all(&root);
+        let source = root.join("build");
+        let terminal = root.join("terminal");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(terminal.join("MQL4")).unwrap();
+        std::fs::write(source.join("Ryiuk.ex4"), b"v1").unwrap();
+        std::fs::write(source.join("Ryiuk.set"), b"lots=0.01").unwrap();
+        std::fs::write(source.join("Ryiuk.ex5"), b"mt5 only").unwrap();
+
+        let log = DeploymentLog::load(root.join("deployments"));
+        let targets = vec![terminal.clone()];
+        let expert = terminal.join("MQL4").join("Experts").join("Ryiuk.ex4");
+
+        let first = log.deploy(&source, &targets, None).unwrap();
+        assert_eq!(first.entries.len(), 2);
+        assert!(first.entries.iter().all(|e| e.action == DeployAction::Created));
+        assert!(terminal.join("MQL4").join("Presets").join("Ryiuk.set").exists());
+
+        std::fs::write(source.join("Ryiuk.ex4"), b"v2").unwrap();
+        let second = log.deploy(&source, &targets, None).unwrap();
+        let ex4 = second.entries.iter().find(|e| e.target == expert).unwrap();
+        assert_eq!(ex4.action, DeployAction::Updated);
+        assert!(second.entries.iter().any(|e| e.action == DeployAction::Skipped));
+        assert_eq!(std::fs::read(&expert).unwrap(), b"v2");
+
+        assert!(log.rollback(first.id).is_err());
+        assert_eq!(std::fs::read(&expert).unwrap(), b"v2");
+        log.rollback(second.id).unwrap();
+        assert_eq!(std::fs::read(&expert).unwrap(), b"v1");
+        log.rollback(first.id).unwrap();
+        assert!(!expert.exists());
+        assert!(log.rollback(first.id).is_err());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_failed_deploy_restores_targets() {
+        let root = std::env::temp_dir().join("daavfx_deploy_fail_test");
+        let _ = std::fs::remove_dir_all(&root);
+        let source = root.join("build");
+        let first = root.join("terminal1");
+        let second = root.join("terminal2");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(first.join("MQL4").join("Experts")).unwrap();
+        std::fs::create_dir_all(second.join("MQL4")).unwrap();
+        std::fs::write(source.join("Ryiuk.ex4"), b"v2").unwrap();
+        let expert = first.join("MQL4").join("Experts").join("Ryiuk.ex4");
+        std::fs::write(&expert, b"v1").unwrap();
+        // A file where the Experts folder should be makes the second copy fail
+        std::fs::write(second.join("MQL4").join("Experts"), b"").unwrap();
+
+        let log = DeploymentLog::load(root.join("deployments"));
+        assert!(log.deploy(&source, &[first.clone(), second.clone()], None).is_err());
+        assert_eq!(std::fs::read(&expert).unwrap(), b"v1");
+        assert!(log.list().is_empty());
+
+        std::fs::remove_file(&expert).unwrap();
+        assert!(log.deploy(&source, &[first, second], None).is_err());
+        assert!(!expert.exists());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,412 @@
+//! Launcher -> EA command channel
+//! Commands are written atomically as sequence-numbered files into the account's `MQL4/Files`
+//! folder; the EA answers with an acknowledgement file. Every command is kept in an audit log.
+//!
+//! Command file `Ryiuk_Cmd_<seq>.txt`: `<seq>,<command>,<key=value;...>,<issued_at>`
+//! Ack file `Ryiuk_Ack_<seq>.txt`:     `<seq>,<ok|error>,<message>`
+
+use crate::pulse::PulseMonitor;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Time the EA gets to acknowledge one attempt
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts before a command is given up
+const MAX_ATTEMPTS: u32 = 3;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_AUDIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    Acknowledged,
+    Rejected,
+    TimedOut,
+}
+
+/// Audit log entry for one command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub seq: u64,
+    pub account: String,
+    pub command: String,
+    pub args: BTreeMap<String, String>,
+    pub issued_at: i64, // Unix timestamp
+    pub completed_at: i64,
+    pub attempts: u32,
+    pub status: CommandStatus,
+    pub response: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EaCommandRequest {
+    pub account: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: BTreeMap<String, serde_json::Value>,
+}
+
+/// Why a command could not be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Invalid(String),
+    UnknownAccount(String),
+    /// Pulse monitor or channel not set up yet
+    NotReady(String),
+    Io(String),
+}
+
+impl CommandError {
+    pub fn status(&self) -> axum::http::StatusCode {
+        match self {
+            CommandError::Invalid(_) => axum::http::StatusCode::BAD_REQUEST,
+            CommandError::UnknownAccount(_) => axum::http::StatusCode::NOT_FOUND,
+            CommandError::NotReady(_) => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            CommandError::Io(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::UnknownAccount(account) => write!(f, "Unknown account: {}", account),
+            CommandError::Invalid(message) | CommandError::NotReady(message) | CommandError::Io(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl From<CommandError> for String {
+    fn from(e: CommandError) -> Self {
+        e.to_string()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ChannelStore {
+    next_seq: HashMap<String, u64>,
+    audit: VecDeque<CommandRecord>,
+}
+
+/// Shared state for the command channel, persisted as `ea_commands.json` in the app data dir
+pub struct EaChannel {
+    path: PathBuf,
+    store: Mutex<ChannelStore>,
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Checks the command against the whitelist and normalises its arguments
+pub fn validate(command: &str, args: &BTreeMap<String, serde_json::Value>) -> Result<BTreeMap<String, String>, String> {
+    let args: BTreeMap<String, String> = args
+        .iter()
+        .map(|(k, v)| {
+            let value = match v {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (k.clone(), value)
+        })
+        .collect();
+
+    // Separators of the file format can't appear inside keys or values
+    if args.iter().any(|(k, v)| [k, v].iter().any(|s| s.contains([',', ';', '=', '\n', '\r']))) {
+        return Err("Command arguments must not contain ',', ';', '=' or newlines".to_string());
+    }
+
+    match command {
+        "pause_trading" | "resume_trading" | "close_all" => Ok(args),
+        "set_lot_multiplier" => {
+            let multiplier: f64 = args
+                .get("multiplier")
+                .ok_or("set_lot_multiplier requires a 'multiplier' argument")?
+                .parse()
+                .map_err(|_| "multiplier must be a number".to_string())?;
+            if !(multiplier > 0.0 && multiplier <= 10.0) {
+                return Err("multiplier must be between 0 and 10".to_string());
+            }
+            Ok(args)
+        }
+        _ => Err(format!("Unknown EA command: {}", command)),
+    }
+}
+
+fn command_line(seq: u64, command: &str, args: &BTreeMap<String, String>, issued_at: i64) -> String {
+    let args: Vec<String> = args.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    format!("{},{},{},{}\n", seq, command, args.join(";"), issued_at)
+}
+
+/// Parses an ack line, returning `(accepted, message)` if it belongs to `seq`
+fn parse_ack(content: &str, seq: u64) -> Option<(bool, String)> {
+    let content = content.trim().trim_start_matches('\u{feff}');
+    let mut parts = content.splitn(3, ',');
+    let ack_seq: u64 = parts.next()?.trim().parse().ok()?;
+    if ack_seq != seq {
+        return None;
+    }
+    let accepted = parts.next()?.trim().eq_ignore_ascii_case("ok");
+    Some((accepted, parts.next().unwrap_or("").trim().to_string()))
+}
+
+/// Writes via a temp file and rename so the EA never sees a half-written command
+fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content).map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to rename {:?}: {}", tmp, e))
+}
+
+impl EaChannel {
+    pub fn load(path: PathBuf) -> Self {
+        let store = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            store: Mutex::new(store),
+        }
+    }
+
+    fn save(&self, store: &ChannelStore) {
+        if let Some(parent) = self.path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let result = serde_json::to_string_pretty(store)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("[EA Channel] Failed to save audit log: {}", e);
+        }
+    }
+
+    fn next_seq(&self, account: &str) -> u64 {
+        let mut store = self.store.lock().unwrap();
+        let seq = store.next_seq.entry(account.to_string()).or_insert(1);
+        let current = *seq;
+        *seq += 1;
+        self.save(&store);
+        current
+    }
+
+    pub fn audit(&self, account: Option<&str>) -> Vec<CommandRecord> {
+        self.store
+            .lock()
+            .unwrap()
+            .audit
+            .iter()
+            .rev()
+            .filter(|r| account.is_none_or(|a| r.account == a))
+            .cloned()
+            .collect()
+    }
+
+    fn record(&self, record: CommandRecord) {
+        let mut store = self.store.lock().unwrap();
+        store.audit.push_back(record);
+        while store.audit.len() > MAX_AUDIT {
+            store.audit.pop_front();
+        }
+        self.save(&store);
+    }
+
+    /// Sends a validated command to the EA watching `files_dir` and waits for its acknowledgement
+    pub async fn send(
+        &self,
+        files_dir: &Path,
+        account: &str,
+        command: &str,
+        args: BTreeMap<String, String>,
+    ) -> Result<CommandRecord, String> {
+        if !files_dir.is_dir() {
+            return Err(format!("MQL Files folder not found for '{}': {:?}", account, files_dir));
+        }
+
+        let seq = self.next_seq(account);
+        let issued_at = unix_now();
+        let cmd_path = files_dir.join(format!("Ryiuk_Cmd_{}.txt", seq));
+        let ack_path = files_dir.join(format!("Ryiuk_Ack_{}.txt", seq));
+        let line = command_line(seq, command, &args, issued_at);
+
+        let mut outcome = None;
+        let mut attempts = 0;
+        while attempts < MAX_ATTEMPTS && outcome.is_none() {
+            attempts += 1;
+            // Re-sending keeps the same sequence number so the EA can ignore duplicates
+            write_atomic(&cmd_path, &line)?;
+
+            let deadline = Instant::now() + ACK_TIMEOUT;
+            while Instant::now() < deadline {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let ack = std::fs::read(&ack_path)
+                    .ok()
+                    .and_then(|bytes| parse_ack(&crate::terminal_discovery::decode_text(&bytes), seq));
+                if let Some(ack) = ack {
+                    let _ = std::fs::remove_file(&ack_path);
+                    outcome = Some(ack);
+                    break;
+                }
+            }
+        }
+
+        // Never leave an unanswered command behind for the EA to execute much later
+        let _ = std::fs::remove_file(&cmd_path);
+
+        let (status, response) = match outcome {
+            Some((true, message)) => (CommandStatus::Acknowledged, message),
+            Some((false, message)) => (CommandStatus::Rejected, message),
+            None => (
+                CommandStatus::TimedOut,
+                format!("No acknowledgement after {} attempts", attempts),
+            ),
+        };
+
+        let record = CommandRecord {
+            seq,
+            account: account.to_string(),
+            command: command.to_string(),
+            args,
+            issued_at,
+            completed_at: unix_now(),
+            attempts,
+            status,
+            response,
+        };
+        self.record(record.clone());
+        Ok(record)
+    }
+}
+
+/// Resolves the account's Files folder from the pulse config and sends the command
+pub async fn dispatch_command<R: Runtime>(app: &AppHandle<R>, request: EaCommandRequest) -> Result<CommandRecord, CommandError> {
+    let args = validate(&request.command, &request.args).map_err(CommandError::Invalid)?;
+    let files_dir = app
+        .try_state::<PulseMonitor>()
+        .ok_or_else(|| CommandError::NotReady("Pulse monitor is not running yet".to_string()))?
+        .files_dir(&request.account)
+        .ok_or_else(|| CommandError::UnknownAccount(request.account.clone()))?;
+    let channel = app
+        .try_state::<EaChannel>()
+        .ok_or_else(|| CommandError::NotReady("EA command channel is not ready yet".to_string()))?;
+    channel
+        .send(&files_dir, &request.account, &request.command, args)
+        .await
+        .map_err(CommandError::Io)
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub async fn send_ea_command<R: Runtime>(
+    account: String,
+    command: String,
+    args: Option<BTreeMap<String, serde_json::Value>>,
+    app_handle: AppHandle<R>,
+) -> Result<CommandRecord, String> {
+    dispatch_command(&app_handle, EaCommandRequest {
+        account,
+        command,
+        args: args.unwrap_or_default(),
+    })
+    .await
+    .map_err(String::from)
+}
+
+#[tauri::command]
+pub fn get_ea_command_log(account: Option<String>, channel: tauri::State<'_, EaChannel>) -> Vec<CommandRecord> {
+    channel.audit(account.as_deref())
+}
+
+// Axum handler for external apps. A command the EA never acknowledged is a 504 with the record as body.
+pub async fn ea_command_http_handler(
+    axum::extract::State(state): axum::extract::State<Arc<(AppHandle, Arc<crate::ai::AIState>)>>,
+    axum::Json(payload): axum::Json<EaCommandRequest>,
+) -> Response {
+    let (app, _) = &*state;
+    match dispatch_command(app, payload).await {
+        Ok(record) if record.status == CommandStatus::TimedOut => {
+            (axum::http::StatusCode::GATEWAY_TIMEOUT, axum::Json(record)).into_response()
+        }
+        Ok(record) => axum::Json(record).into_response(),
+        Err(e) => (e.status(), e.to_string()).into_response(),
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut args = BTreeMap::new();
+        assert!(validate("close_all", &args).is_ok());
+        assert!(validate("rm_rf", &args).is_err());
+        assert!(validate("set_lot_multiplier", &args).is_err());
+
+        args.insert("multiplier".to_string(), serde_json::json!(1.5));
+        assert_eq!(validate("set_lot_multiplier", &args).unwrap()["multiplier"], "1.5");
+        args.insert("multiplier".to_string(), serde_json::json!("0;close_all"));
+        assert!(validate("set_lot_multiplier", &args).is_err());
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(CommandError::UnknownAccount("demo".into()).status(), axum::http::StatusCode::NOT_FOUND);
+        assert_eq!(CommandError::Io("disk full".into()).status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(CommandError::Invalid("bad".into()).status(), axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(String::from(CommandError::UnknownAccount("demo".into())), "Unknown account: demo");
+    }
+
+    #[test]
+    fn test_command_and_ack_format() {
+        let args = BTreeMap::from([("multiplier".to_string(), "2".to_string())]);
+        assert_eq!(command_line(7, "set_lot_multiplier", &args, 1700000000), "7,set_lot_multiplier,multiplier=2,1700000000\n");
+
+        assert_eq!(parse_ack("7,ok,multiplier set\r\n", 7), Some((true, "multiplier set".to_string())));
+        assert_eq!(parse_ack("7,error,not allowed, market closed", 7), Some((false, "not allowed, market closed".to_string())));
+        assert_eq!(parse_ack("6,ok,", 7), None);
+    }
+
+    #[tokio::test]
+    async fn test_send_waits_for_ack() {
+        let dir = std::env::temp_dir().join("daavfx_ea_channel_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let channel = EaChannel::load(dir.join("audit.json"));
+
+        // Fake EA: consume the command file and acknowledge it
+        let files = dir.clone();
+        tokio::spawn(async move {
+            let cmd = files.join("Ryiuk_Cmd_1.txt");
+            while !cmd.exists() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            let line = std::fs::read_to_string(&cmd).unwrap();
+            assert!(line.starts_with("1,pause_trading,,"));
+            std::fs::remove_file(&cmd).unwrap();
+            std::fs::write(files.join("Ryiuk_Ack_1.txt"), "1,ok,paused").unwrap();
+        });
+
+        let record = channel.send(&dir, "demo", "pause_trading", BTreeMap::new()).await.unwrap();
+        assert_eq!(record.status, CommandStatus::Acknowledged);
+        assert_eq!(record.response, "paused");
+        assert_eq!(record.attempts, 1);
+        assert!(!dir.join("Ryiuk_Ack_1.txt").exists());
+        assert_eq!(channel.audit(Some("demo")).len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
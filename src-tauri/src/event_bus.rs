@@ -0,0 +1,342 @@
+//! WebSocket event bus
+//! Mirrors the launcher's Tauri events to external apps on `GET /events`. Clients subscribe to
+//! topics (`logs:<app_id>`, `pulse:<account_id>`, `portfolio`, `apps:<app_id>`, `alerts`,
+//! `services`, `models`; a trailing `*` matches a prefix) and get the last messages of each topic replayed.
+//! Browsers can't send the bearer header on a handshake and pass `?access_token=` instead.
+//!
+//! Client → server: `{"op": "subscribe", "topics": ["pulse:*"], "replay": 10}` / `{"op": "unsubscribe", ...}`
+//! Server → client: `{"type": "event", ...}`, `{"type": "subscribed", ...}`, `{"type": "lagged", "skipped": n}`,
+//! `{"type": "error", "message": ...}`
+
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::{broadcast, watch};
+
+/// Messages kept per topic for replay
+pub const REPLAY_DEPTH: usize = 50;
+/// Messages a subscriber may fall behind before it starts losing them
+const CHANNEL_CAPACITY: usize = 1024;
+/// A client that can't take a message within this long is disconnected
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TOPICS: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BusMessage {
+    pub seq: u64,
+    pub topic: String,
+    /// Name of the mirrored Tauri event
+    pub event: String,
+    pub payload: serde_json::Value,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<BusMessage>>,
+    inner: Mutex<BusInner>,
+}
+
+#[derive(Default)]
+struct BusInner {
+    next_seq: u64,
+    retained: HashMap<String, VecDeque<Arc<BusMessage>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            inner: Mutex::new(BusInner::default()),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, topic: &str, event: &str, payload: serde_json::Value, timestamp: i64) -> Arc<BusMessage> {
+        // Sending under the lock keeps the broadcast order equal to the seq order
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_seq += 1;
+        let message = Arc::new(BusMessage {
+            seq: inner.next_seq,
+            topic: topic.to_string(),
+            event: event.to_string(),
+            payload,
+            timestamp,
+        });
+        let retained = inner.retained.entry(topic.to_string()).or_default();
+        if retained.len() >= REPLAY_DEPTH {
+            retained.pop_front();
+        }
+        retained.push_back(message.clone());
+        let _ = self.sender.send(message.clone());
+        message
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BusMessage>> {
+        self.sender.subscribe()
+    }
+
+    /// The last `limit` retained messages of every topic matching `patterns`, oldest first
+    pub fn replay(&self, patterns: &[String], limit: usize) -> Vec<Arc<BusMessage>> {
+        let inner = self.inner.lock().unwrap();
+        let mut messages: Vec<Arc<BusMessage>> = inner
+            .retained
+            .iter()
+            .filter(|(topic, _)| patterns.iter().any(|p| topic_matches(p, topic)))
+            .flat_map(|(_, retained)| retained.iter().skip(retained.len().saturating_sub(limit)).cloned())
+            .collect();
+        messages.sort_by_key(|m| m.seq);
+        messages
+    }
+}
+
+/// `pulse:*` matches every pulse topic, `*` matches everything
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+/// Emits a Tauri event to the webview and mirrors it to WebSocket subscribers of `topic`
+pub fn emit<R: Runtime, S: Serialize + Clone>(app: &AppHandle<R>, topic: &str, event: &str, payload: S) {
+    if let Some(bus) = app.try_state::<Arc<EventBus>>() {
+        if let Ok(value) = serde_json::to_value(&payload) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default();
+            bus.publish(topic, event, value, timestamp);
+        }
+    }
+    let _ = app.emit(event, payload);
+}
+
+// ============ Protocol ============
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientRequest {
+    Subscribe {
+        topics: Vec<String>,
+        /// Retained messages to replay per topic (default all, capped at `REPLAY_DEPTH`)
+        #[serde(default)]
+        replay: Option<usize>,
+    },
+    Unsubscribe {
+        topics: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage<'a> {
+    Event(&'a BusMessage),
+    Subscribed { topics: &'a [String] },
+    /// The client fell behind and `skipped` messages were dropped
+    Lagged { skipped: u64 },
+    Error { message: String },
+}
+
+/// One client's topic patterns and how far each topic has been delivered
+#[derive(Default)]
+pub struct Subscription {
+    patterns: Vec<String>,
+    delivered: HashMap<String, u64>,
+}
+
+impl Subscription {
+    pub fn add(&mut self, topics: &[String]) -> Result<(), String> {
+        if topics.iter().any(|t| t.is_empty()) {
+            return Err("Topics must not be empty".to_string());
+        }
+        for topic in topics {
+            if !self.patterns.contains(topic) {
+                self.patterns.push(topic.clone());
+            }
+        }
+        if self.patterns.len() > MAX_TOPICS {
+            self.patterns.truncate(MAX_TOPICS);
+            return Err(format!("At most {} topics per connection", MAX_TOPICS));
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, topics: &[String]) {
+        self.patterns.retain(|p| !topics.contains(p));
+    }
+
+    /// Whether `message` should go out; replayed messages are not sent again when they also arrive live
+    pub fn accept(&mut self, message: &BusMessage) -> bool {
+        if !self.patterns.iter().any(|p| topic_matches(p, &message.topic)) {
+            return false;
+        }
+        let delivered = self.delivered.entry(message.topic.clone()).or_default();
+        if message.seq <= *delivered {
+            return false;
+        }
+        *delivered = message.seq;
+        true
+    }
+}
+
+// ============ HTTP ============
+
+type ServerState = State<Arc<(AppHandle, Arc<crate::ai::AIState>)>>;
+
+/// GET /events (WebSocket upgrade)
+pub async fn events_ws_handler(ws: WebSocketUpgrade, State(state): ServerState) -> Response {
+    let bus = state.0.state::<Arc<EventBus>>().inner().clone();
+    let shutdown = state.0.state::<crate::server::ServerHandle>().shutdown_signal();
+    ws.on_upgrade(move |socket| run_socket(socket, bus, shutdown))
+}
+
+async fn send(socket: &mut futures_util::stream::SplitSink<WebSocket, Message>, message: &ServerMessage<'_>) -> bool {
+    let Ok(text) = serde_json::to_string(message) else {
+        return true;
+    };
+    matches!(tokio::time::timeout(SEND_TIMEOUT, socket.send(Message::Text(text.into()))).await, Ok(Ok(())))
+}
+
+async fn run_socket(socket: WebSocket, bus: Arc<EventBus>, mut shutdown: watch::Receiver<bool>) {
+    let (mut sink, mut stream) = socket.split();
+    let mut live = bus.subscribe();
+    let mut subscription = Subscription::default();
+
+    loop {
+        tokio::select! {
+            incoming = stream.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let request = match serde_json::from_str::<ClientRequest>(text.as_str()) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        if !send(&mut sink, &ServerMessage::Error { message: format!("Invalid request: {}", e) }).await {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                let ok = match request {
+                    ClientRequest::Subscribe { topics, replay } => match subscription.add(&topics) {
+                        Ok(()) => {
+                            let mut ok = send(&mut sink, &ServerMessage::Subscribed { topics: &topics }).await;
+                            let limit = replay.unwrap_or(REPLAY_DEPTH).min(REPLAY_DEPTH);
+                            for message in bus.replay(&topics, limit) {
+                                if ok && subscription.accept(&message) {
+                                    ok = send(&mut sink, &ServerMessage::Event(&message)).await;
+                                }
+                            }
+                            ok
+                        }
+                        Err(message) => send(&mut sink, &ServerMessage::Error { message }).await,
+                    },
+                    ClientRequest::Unsubscribe { topics } => {
+                        subscription.remove(&topics);
+                        true
+                    }
+                };
+                if !ok {
+                    break;
+                }
+            }
+            received = live.recv() => {
+                let ok = match received {
+                    Ok(message) if subscription.accept(&message) => send(&mut sink, &ServerMessage::Event(&message)).await,
+                    Ok(_) => true,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        send(&mut sink, &ServerMessage::Lagged { skipped }).await
+                    }
+                    Err(broadcast::error::RecvError::Closed) => false,
+                };
+                if !ok {
+                    break;
+                }
+            }
+            // Close right away so server shutdown doesn't wait out the drain timeout on open sockets
+            _ = shutdown.wait_for(|stop| *stop) => {
+                let close = Message::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "Server shutting down".into(),
+                }));
+                let _ = tokio::time::timeout(SEND_TIMEOUT, sink.send(close)).await;
+                break;
+            }
+        }
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("pulse:live", "pulse:live"));
+        assert!(!topic_matches("pulse:live", "pulse:demo"));
+        assert!(topic_matches("pulse:*", "pulse:demo"));
+        assert!(!topic_matches("pulse:*", "portfolio"));
+        assert!(topic_matches("*", "alerts"));
+    }
+
+    #[test]
+    fn test_replay_and_dedupe() {
+        let bus = EventBus::default();
+        let mut live = bus.subscribe();
+        for i in 0..REPLAY_DEPTH + 3 {
+            bus.publish("pulse:live", "account-pulse", serde_json::json!({ "equity": i }), 0);
+        }
+        bus.publish("logs:dashboard", "app-log", serde_json::json!("ready"), 0);
+
+        let patterns = vec!["pulse:*".to_string()];
+        let replayed = bus.replay(&patterns, 2);
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[1].payload["equity"], REPLAY_DEPTH + 2);
+        assert_eq!(bus.replay(&patterns, usize::MAX).len(), REPLAY_DEPTH);
+
+        // Live messages already covered by the replay are skipped
+        let mut subscription = Subscription::default();
+        subscription.add(&patterns).unwrap();
+        for message in &replayed {
+            assert!(subscription.accept(message));
+        }
+        let mut delivered = 0;
+        while let Ok(message) = live.try_recv() {
+            if subscription.accept(&message) {
+                delivered += 1;
+            }
+        }
+        assert_eq!(delivered, 0);
+
+        let fresh = bus.publish("pulse:live", "account-pulse", serde_json::json!({}), 0);
+        assert!(subscription.accept(&fresh));
+        subscription.remove(&patterns);
+        let after = bus.publish("pulse:live", "account-pulse", serde_json::json!({}), 0);
+        assert!(!subscription.accept(&after));
+    }
+
+    #[test]
+    fn test_client_request_parsing() {
+        let request: ClientRequest =
+            serde_json::from_str(r#"{"op": "subscribe", "topics": ["logs:dashboard"], "replay": 5}"#).unwrap();
+        assert!(matches!(request, ClientRequest::Subscribe { replay: Some(5), .. }));
+        assert!(serde_json::from_str::<ClientRequest>(r#"{"op": "publish"}"#).is_err());
+
+        let mut subscription = Subscription::default();
+        assert!(subscription.add(&[String::new()]).is_err());
+        let many: Vec<String> = (0..MAX_TOPICS + 1).map(|i| format!("logs:{}", i)).collect();
+        assert!(subscription.add(&many).is_err());
+    }
+}
@@ -5,14 +5,38 @@ use serde::Serialize;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri::{AppHandle, Manager, Runtime, State};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
-use axum::{routing::post, Router};
-use tower_http::cors::{Any, CorsLayer};
+use axum::{routing::{get, post}, Router};
 
 mod ai;
+mod ai_agent;
+mod ai_chat;
+mod ai_diagnose;
+mod ai_model_files;
+mod ai_models;
+mod ai_openai;
+mod ai_params;
+mod ai_prompt;
+mod ai_retrieval;
+mod ai_worker;
+mod alerts;
+mod app_control;
+mod deploy;
+mod ea_channel;
+mod event_bus;
+mod metrics;
+mod pulse;
+mod server;
+mod services;
+mod terminal_discovery;
+mod terminal_logs;
+mod terminals;
+
+use app_control::ControlError;
+use terminals::{TerminalKind, TerminalStore};
 
 #[derive(Clone, Serialize)]
 struct LogPayload {
@@ -22,14 +46,6 @@ struct LogPayload {
     log_type: String, // "info", "error", "warn"
 }
 
-#[derive(Clone, Serialize)]
-struct PulsePayload {
-    balance: String,
-    equity: String,
-    drawdown: String,
-    timestamp: i64,
-}
-
 struct ProcessRegistry {
     children: DashMap<String, Arc<Mutex<Child>>>,
 }
@@ -40,8 +56,28 @@ impl ProcessRegistry {
             children: DashMap::new(),
         }
     }
+
+    /// Whether `app_id` is tracked and its process is still alive.
+    /// Entries whose process already exited are dropped.
+    fn is_running(&self, app_id: &str) -> bool {
+        let exited = match self.children.get(app_id) {
+            None => return false,
+            // Locked means someone is killing or waiting on it right now
+            Some(child) => match child.try_lock() {
+                Ok(mut child) => !matches!(child.try_wait(), Ok(None)),
+                Err(_) => false,
+            },
+        };
+        if exited {
+            self.children.remove(app_id);
+        }
+        !exited
+    }
 }
 
+/// Every app with a reserved Vite dev server port
+const APP_IDS: &[&str] = &["launcher", "mql_fixer", "copytrader", "dashboard", "charting", "backtester", "quantum_bt"];
+
 /// Returns the reserved Vite dev server port for each app.
 /// Each app MUST have a unique port. If blocked, it's a zombie.
 fn get_app_port(app_id: &str) -> Option<u16> {
@@ -56,6 +92,18 @@ fn get_app_port(app_id: &str) -> Option<u16> {
     }
 }
 
+/// Returns each app's project folder, relative to the APPS root.
+fn get_app_dir(app_id: &str) -> Option<&'static str> {
+    match app_id {
+        "quantum_bt" => Some("quantum_bt_daavfx"),
+        "charting" | "backtester" => Some("charting_daavfx"),
+        "copytrader" => Some("copytrader_ui"),
+        "dashboard" => Some("dashboard/logic-canvas-main"),
+        "mql_fixer" => Some("rust_mql_fixer"),
+        _ => None,
+    }
+}
+
 /// Kills any process using the specified port (Windows only).
 /// This is used to auto-kill zombie Vite dev servers from previous runs.
 #[cfg(target_os = "windows")]
@@ -125,17 +173,31 @@ async fn launch_app<R: Runtime>(
     app_handle: AppHandle<R>,
     registry: State<'_, ProcessRegistry>,
 ) -> Result<(), String> {
+    start_app(&app_id, &app_handle, &registry).await.map_err(String::from)
+}
+
+/// (Re)starts an app's dev server. Shared by the `launch_app` command and `POST /apps/{id}/launch`.
+async fn start_app<R: Runtime>(
+    app_id: &str,
+    app_handle: &AppHandle<R>,
+    registry: &ProcessRegistry,
+) -> Result<(), ControlError> {
+    let dir_name = get_app_dir(app_id).ok_or_else(|| ControlError::UnknownApp(app_id.to_string()))?;
+    let cmd_str = "npm run tauri dev";
+
     // STEP 1: Kill any zombie process on this app's reserved port
-    if let Some(port) = get_app_port(&app_id) {
-        kill_zombie_on_port(port);
+    // netstat/taskkill block, so keep them off the async workers (this also runs from axum handlers)
+    if let Some(port) = get_app_port(app_id) {
+        let _ = tauri::async_runtime::spawn_blocking(move || kill_zombie_on_port(port)).await;
         // Small delay to allow port release
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
     }
 
     // STEP 2: Kill our tracked child if it exists in registry
     // Check if already running - if so, Kill it (force restart)
-    if registry.children.contains_key(&app_id) {
-        if let Some((_, child_arc)) = registry.children.remove(&app_id) {
+    if registry.children.contains_key(app_id) {
+        if let Some((_, child_arc)) = registry.children.remove(app_id) {
+            metrics::global().inc(metrics::APP_RESTARTS, &[("app", app_id)]);
             let mut child = child_arc.lock().await;
             
              // Best effort kill
@@ -150,20 +212,11 @@ async fn launch_app<R: Runtime>(
         }
     }
 
-    let base_path = get_apps_base_path(&app_handle)?;
-    
-    let (dir_name, cmd_str) = match app_id.as_str() {
-        "quantum_bt" => ("quantum_bt_daavfx", "npm run tauri dev"),
-        "charting" | "backtester" => ("charting_daavfx", "npm run tauri dev"),
-        "copytrader" => ("copytrader_ui", "npm run tauri dev"),
-        "dashboard" => ("dashboard/logic-canvas-main", "npm run tauri dev"),
-        "mql_fixer" => ("rust_mql_fixer", "npm run tauri dev"),
-        _ => return Err(format!("Unknown app ID: {}", app_id)),
-    };
+    let base_path = get_apps_base_path(app_handle)?;
 
     let app_path = base_path.join(dir_name);
     if !app_path.exists() {
-        return Err(format!("App directory not found: {:?}", app_path));
+        return Err(ControlError::Failed(format!("App directory not found: {:?}", app_path)));
     }
 
     // On Windows, complex commands like "npm run ..." are best run through cmd /C
@@ -174,6 +227,7 @@ async fn launch_app<R: Runtime>(
     
     // FATAL FIX: Inject Cargo path explicitly for child processes
     // The launcher environment might have it, but we ensure the child has it too.
+    #[cfg(target_os = "windows")]
     if let Ok(path_val) = std::env::var("PATH") {
         let cargo_bin = format!("{}\\.cargo\\bin", std::env::var("USERPROFILE").unwrap_or_default());
         command.env("PATH", format!("{};{}", path_val, cargo_bin));
@@ -184,8 +238,22 @@ async fn launch_app<R: Runtime>(
     #[cfg(not(target_os = "windows"))]
     command.args(&["-c", cmd_str]);
 
+    command.current_dir(&app_path);
+
+    spawn_tracked(app_id.to_string(), command, app_handle, registry)?;
+    metrics::global().inc(metrics::APP_LAUNCHES, &[("app", app_id)]);
+    Ok(())
+}
+
+/// Spawns `command`, registers it in the `ProcessRegistry` under `app_id`
+/// and forwards its stdout/stderr as `app-log` events.
+fn spawn_tracked<R: Runtime>(
+    app_id: String,
+    mut command: Command,
+    app_handle: &AppHandle<R>,
+    registry: &ProcessRegistry,
+) -> Result<(), String> {
     command
-        .current_dir(&app_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .stdin(Stdio::null());
@@ -197,6 +265,7 @@ async fn launch_app<R: Runtime>(
 
     let child_arc = Arc::new(Mutex::new(child));
     registry.children.insert(app_id.clone(), child_arc);
+    app_control::emit_app_state(app_handle, &app_id, true);
 
     // Spawn monitoring tasks for stdout/stderr
     let h_stdout = app_handle.clone();
@@ -204,7 +273,7 @@ async fn launch_app<R: Runtime>(
     tauri::async_runtime::spawn(async move {
         let mut reader = BufReader::new(stdout).lines();
         while let Ok(Some(line)) = reader.next_line().await {
-            let _ = h_stdout.emit("app-log", LogPayload {
+            app_control::record_log(&h_stdout, LogPayload {
                 app_id: id_stdout.clone(),
                 message: line,
                 log_type: "info".to_string(),
@@ -217,7 +286,7 @@ async fn launch_app<R: Runtime>(
     tauri::async_runtime::spawn(async move {
         let mut reader = BufReader::new(stderr).lines();
         while let Ok(Some(line)) = reader.next_line().await {
-            let _ = h_stderr.emit("app-log", LogPayload {
+            app_control::record_log(&h_stderr, LogPayload {
                 app_id: id_stderr.clone(),
                 message: line,
                 log_type: "error".to_string(),
@@ -229,24 +298,71 @@ async fn launch_app<R: Runtime>(
 }
 
 #[tauri::command]
-async fn kill_app(app_id: String, registry: State<'_, ProcessRegistry>) -> Result<(), String> {
-    if let Some((_, child_mutex)) = registry.children.remove(&app_id) {
+async fn kill_app<R: Runtime>(
+    app_id: String,
+    app_handle: AppHandle<R>,
+    registry: State<'_, ProcessRegistry>,
+) -> Result<(), String> {
+    stop_app(&app_id, &app_handle, &registry).await.map_err(String::from)
+}
+
+/// Kills a tracked app. Shared by the `kill_app` command and `POST /apps/{id}/stop`.
+async fn stop_app<R: Runtime>(
+    app_id: &str,
+    app_handle: &AppHandle<R>,
+    registry: &ProcessRegistry,
+) -> Result<(), ControlError> {
+    if let Some((_, child_mutex)) = registry.children.remove(app_id) {
         let mut child = child_mutex.lock().await;
-        child.kill().await.map_err(|e| format!("Failed to kill {}: {}", app_id, e))?;
+        child
+            .kill()
+            .await
+            .map_err(|e| ControlError::Failed(format!("Failed to kill {}: {}", app_id, e)))?;
+        app_control::emit_app_state(app_handle, app_id, false);
         Ok(())
     } else {
-        Err(format!("App '{}' is not running", app_id))
+        Err(ControlError::NotRunning(app_id.to_string()))
+    }
+}
+
+/// Starts a configured MT4/MT5 terminal and tracks it like any other app.
+/// Unlike `launch_app` this never force-restarts: killing a live terminal could interrupt trading.
+fn launch_terminal<R: Runtime>(
+    kind: TerminalKind,
+    terminal_id: Option<String>,
+    app_handle: &AppHandle<R>,
+    registry: &ProcessRegistry,
+    store: &TerminalStore,
+) -> Result<(), String> {
+    let terminal = store.find(kind, terminal_id.as_deref())?;
+    let app_id = terminal.app_id();
+
+    if registry.is_running(&app_id) {
+        return Err(format!("{} terminal '{}' is already running", kind.label(), terminal.id));
     }
+
+    let command = terminal.build_command()?;
+    spawn_tracked(app_id, command, app_handle, registry)
 }
 
 #[tauri::command]
-fn launch_mt4() -> Result<(), String> {
-    Ok(())
+async fn launch_mt4<R: Runtime>(
+    terminal_id: Option<String>,
+    app_handle: AppHandle<R>,
+    registry: State<'_, ProcessRegistry>,
+    store: State<'_, TerminalStore>,
+) -> Result<(), String> {
+    launch_terminal(TerminalKind::Mt4, terminal_id, &app_handle, &registry, &store)
 }
 
 #[tauri::command]
-fn launch_mt5() -> Result<(), String> {
-    Ok(())
+async fn launch_mt5<R: Runtime>(
+    terminal_id: Option<String>,
+    app_handle: AppHandle<R>,
+    registry: State<'_, ProcessRegistry>,
+    store: State<'_, TerminalStore>,
+) -> Result<(), String> {
+    launch_terminal(TerminalKind::Mt5, terminal_id, &app_handle, &registry, &store)
 }
 
 fn main() {
@@ -256,68 +372,166 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(ProcessRegistry::new())
+        .manage(app_control::LogBuffer::default())
+        .manage(Arc::new(event_bus::EventBus::default()))
         .manage(ai_state_for_tauri)
         .setup(move |app| {
             let handle = app.handle().clone();
+
+            let alerts_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("alerts.json"))
+                .unwrap_or_else(|_| PathBuf::from("alerts.json"));
+            app.manage(alerts::AlertEngine::load(alerts_path));
+
+            let terminals_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("terminals.json"))
+                .unwrap_or_else(|_| PathBuf::from("terminals.json"));
+            app.manage(TerminalStore::load(terminals_path));
+            tauri::async_runtime::spawn(terminal_logs::run_log_tailer(handle.clone()));
+
+            let deployments_dir = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("deployments"))
+                .unwrap_or_else(|_| PathBuf::from("deployments"));
+            app.manage(deploy::DeploymentLog::load(deployments_dir));
+
+            let ea_commands_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("ea_commands.json"))
+                .unwrap_or_else(|_| PathBuf::from("ea_commands.json"));
+            app.manage(ea_channel::EaChannel::load(ea_commands_path));
+
+            let models_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("models.json"))
+                .unwrap_or_else(|_| PathBuf::from("models.json"));
+            ai_state.models.load_config(&models_path);
+
+            let model_checks_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(ai_model_files::CHECKS_FILE))
+                .unwrap_or_else(|_| PathBuf::from(ai_model_files::CHECKS_FILE));
+            app.manage(Arc::new(ai_model_files::ModelFiles::load(model_checks_path)));
+
+            let conversations_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("conversations.json"))
+                .unwrap_or_else(|_| PathBuf::from("conversations.json"));
+            app.manage(ai_chat::ConversationStore::load(conversations_path));
+            tauri::async_runtime::spawn(ai_chat::run_session_sweeper(ai_state.clone()));
+
+            let retrieval_config_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(ai_retrieval::CONFIG_FILE))
+                .unwrap_or_else(|_| PathBuf::from(ai_retrieval::CONFIG_FILE));
+            let retrieval_index_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(ai_retrieval::INDEX_FILE))
+                .unwrap_or_else(|_| PathBuf::from(ai_retrieval::INDEX_FILE));
+            let retrieval_index = Arc::new(ai_retrieval::RetrievalIndex::load(retrieval_config_path, retrieval_index_path));
+            app.manage(retrieval_index.clone());
+            // Pick up documents edited while the launcher was closed
+            tauri::async_runtime::spawn_blocking(move || {
+                if let Err(e) = retrieval_index.rebuild() {
+                    eprintln!("Document index rebuild failed: {}", e);
+                }
+            });
+
+            let server_config_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("server.json"))
+                .unwrap_or_else(|_| PathBuf::from("server.json"));
+            let server_config = server::ServerConfig::load(&server_config_path);
+            let token_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(server::TOKEN_FILE))
+                .unwrap_or_else(|_| PathBuf::from(server::TOKEN_FILE));
+            let token = server::load_or_create_token(&token_path).unwrap_or_else(|e| {
+                // Still protect the server for this session even if other apps can't read the token
+                eprintln!("API token not persisted: {}", e);
+                rand::random::<[u8; 32]>().iter().map(|b| format!("{:02x}", b)).collect()
+            });
+            let guard = Arc::new(server::ApiGuard::new(token, &server_config));
+
             let shared_context = Arc::new((handle.clone(), ai_state));
 
+            let endpoint_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(server::ENDPOINT_FILE))
+                .unwrap_or_else(|_| PathBuf::from(server::ENDPOINT_FILE));
+            app.manage(server::ServerHandle::new(server_config, endpoint_path));
+
             // Start AI HTTP Server for other apps (Dashboard, etc.)
-            tauri::async_runtime::spawn(async move {
-                let cors = CorsLayer::new()
-                    .allow_origin(Any)
-                    .allow_methods(Any)
-                    .allow_headers(Any);
-
-                let app = Router::new()
-                    .route("/ai/ask", post(ai::ai_http_handler))
-                    .layer(cors)
-                    .with_state(shared_context);
-
-                println!("AI singleton server listening on http://0.0.0.0:3030");
-                let listener = tokio::net::TcpListener::bind("0.0.0.0:3030").await.unwrap();
-                axum::serve(listener, app).await.unwrap();
-            });
+            let router = Router::new()
+                .route("/ai/ask", post(ai::ai_http_handler))
+                .route("/ai/stream", post(ai::ai_stream_http_handler))
+                .route("/ai/cancel", post(ai::ai_cancel_http_handler))
+                .route("/ai/status", get(ai::ai_status_http_handler))
+                .route("/ai/agent", post(ai_agent::agent_http_handler))
+                .route(
+                    "/ai/conversations",
+                    get(ai_chat::list_conversations_http_handler).post(ai_chat::create_conversation_http_handler),
+                )
+                .route(
+                    "/ai/conversations/{id}",
+                    get(ai_chat::get_conversation_http_handler).delete(ai_chat::delete_conversation_http_handler),
+                )
+                .route("/ai/conversations/{id}/messages", post(ai_chat::chat_message_http_handler))
+                .route("/ai/conversations/{id}/truncate", post(ai_chat::truncate_conversation_http_handler))
+                .route("/v1/models", get(ai_openai::models_handler))
+                .route("/v1/chat/completions", post(ai_openai::chat_completions_handler))
+                .route("/v1/completions", post(ai_openai::completions_handler))
+                .route("/ea/command", post(ea_channel::ea_command_http_handler))
+                .route("/services", get(services::services_http_handler))
+                .route("/apps", get(app_control::apps_http_handler))
+                .route("/apps/{id}/launch", post(app_control::launch_app_http_handler))
+                .route("/apps/{id}/stop", post(app_control::stop_app_http_handler))
+                .route("/apps/{id}/logs", get(app_control::app_logs_http_handler))
+                .route("/events", get(event_bus::events_ws_handler))
+                .route("/metrics", get(metrics::metrics_http_handler))
+                .layer(axum::middleware::from_fn_with_state(guard, server::guard_middleware))
+                .layer(server::cors_layer())
+                .with_state(shared_context);
+            server::spawn(handle.clone(), router);
 
             // Start MT4 Pulse Monitor
-            let h_pulse = handle.clone();
             let base_path_pulse = get_apps_base_path(&handle).unwrap_or_else(|_| PathBuf::from("."));
-            tauri::async_runtime::spawn(async move {
-                let pulse_path = base_path_pulse
-                    .parent().unwrap_or(&base_path_pulse) // level up from APPS to main_ecosystem_trading
-                    .join("trading_algorithms")
-                    .join("mt4_implementation")
-                    .join("MT4")
-                    .join("MQL4")
-                    .join("Files")
-                    .join("Ryiuk_AccountPulse.csv");
-
-                println!("Monitoring MT4 Pulse at: {:?}", pulse_path);
-                
-                let mut last_processed_time = 0;
-                let mut interval = tokio::time::interval(std::time::Duration::from_millis(1000));
-                
-                loop {
-                    interval.tick().await;
-                    if pulse_path.exists() {
-                        if let Ok(content) = std::fs::read_to_string(&pulse_path) {
-                            let parts: Vec<&str> = content.trim().split(',').collect();
-                            if parts.len() >= 4 {
-                                let timestamp: i64 = parts[3].parse().unwrap_or(0);
-                                if timestamp > last_processed_time {
-                                    let _ = h_pulse.emit("account-pulse", PulsePayload {
-                                        balance: parts[0].to_string(),
-                                        equity: parts[1].to_string(),
-                                        drawdown: parts[2].to_string(),
-                                        timestamp,
-                                    });
-                                    last_processed_time = timestamp;
-                                }
-                            }
-                        }
-                    }
-                }
-            });
+            let pulse_path = base_path_pulse
+                .parent().unwrap_or(&base_path_pulse) // level up from APPS to main_ecosystem_trading
+                .join("trading_algorithms")
+                .join("mt4_implementation")
+                .join("MT4")
+                .join("MQL4")
+                .join("Files")
+                .join("Ryiuk_AccountPulse.csv");
+
+            let pulse_config_path = handle
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join("pulse_sources.json"))
+                .unwrap_or_else(|_| PathBuf::from("pulse_sources.json"));
+            let pulse_config = pulse::PulseConfig::load(&pulse_config_path, pulse_path);
+            for source in &pulse_config.sources {
+                println!("Monitoring pulse for '{}' at: {:?}", source.account_id, source.path);
+            }
+            app.manage(pulse::PulseMonitor::new(pulse_config_path, pulse_config));
+            tauri::async_runtime::spawn(pulse::run_monitor(handle.clone()));
 
             Ok(())
         })
@@ -326,8 +540,56 @@ fn main() {
             kill_app,
             launch_mt4,
             launch_mt5,
-            ai::ask_local_ai
+            ai::ask_local_ai,
+            ai::ask_local_ai_stream,
+            ai::cancel_ai_request,
+            ai::ai_queue_status,
+            ai_agent::ask_agent,
+            ai_diagnose::explain_app_failure,
+            ai_retrieval::retrieval_status,
+            ai_retrieval::set_retrieval_config,
+            ai_retrieval::rebuild_retrieval_index,
+            ai_retrieval::search_documents,
+            ai_chat::create_conversation,
+            ai_chat::list_conversations,
+            ai_chat::get_conversation,
+            ai_chat::send_chat_message,
+            ai_chat::truncate_conversation,
+            ai_chat::delete_conversation,
+            ai_models::list_models,
+            ai_models::load_model,
+            ai_models::unload_model,
+            ai_model_files::import_model,
+            ai_model_files::model_status,
+            alerts::list_alert_rules,
+            alerts::save_alert_rule,
+            alerts::delete_alert_rule,
+            alerts::get_alert_history,
+            alerts::acknowledge_alert,
+            alerts::acknowledge_all_alerts,
+            pulse::pulse_status,
+            pulse::get_portfolio_pulse,
+            pulse::get_pulse_config,
+            pulse::set_pulse_config,
+            terminals::list_terminals,
+            terminals::save_terminal,
+            terminals::remove_terminal,
+            terminal_discovery::detect_terminals,
+            deploy::deploy_ea,
+            deploy::list_deployments,
+            deploy::rollback_deployment,
+            ea_channel::send_ea_command,
+            ea_channel::get_ea_command_log,
+            server::service_status,
+            services::list_services
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Some(server) = app.try_state::<server::ServerHandle>() {
+                    server.shutdown(server::DRAIN_TIMEOUT);
+                }
+            }
+        });
 }
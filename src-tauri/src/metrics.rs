@@ -0,0 +1,295 @@
+//! Prometheus metrics
+//! Counters and histograms are recorded where things happen; gauges (running apps, pulse readings,
+//! the AI queue) are read from live state on every scrape of `GET /metrics`. Scrapers need the
+//! API token like any other client (`authorization.credentials_file` in Prometheus).
+
+use crate::ai_params::Usage;
+use crate::ProcessRegistry;
+use axum::response::{IntoResponse, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+
+pub const APP_LAUNCHES: &str = "launcher_app_launches_total";
+pub const APP_RESTARTS: &str = "launcher_app_restarts_total";
+pub const APP_LOG_LINES: &str = "launcher_app_log_lines_total";
+pub const AI_REQUESTS: &str = "ai_inference_requests_total";
+pub const AI_PROMPT_TOKENS: &str = "ai_prompt_tokens_total";
+pub const AI_GENERATED_TOKENS: &str = "ai_generated_tokens_total";
+pub const AI_DURATION: &str = "ai_inference_duration_seconds";
+pub const AI_FIRST_TOKEN: &str = "ai_time_to_first_token_seconds";
+
+/// Upper bounds, in seconds, of the latency histogram buckets
+const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+fn help(name: &str) -> &'static str {
+    match name {
+        APP_LAUNCHES => "Apps started by the launcher",
+        APP_RESTARTS => "Launches that replaced a running instance of the app",
+        APP_LOG_LINES => "Log lines received from each app",
+        AI_REQUESTS => "Inference runs by outcome",
+        AI_PROMPT_TOKENS => "Prompt tokens fed to the model",
+        AI_GENERATED_TOKENS => "Tokens generated by the model",
+        AI_DURATION => "Wall time of an inference run",
+        AI_FIRST_TOKEN => "Time until the first generated token",
+        _ => "",
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+fn owned(labels: &[(&'static str, &str)]) -> Labels {
+    labels.iter().map(|(k, v)| (*k, v.to_string())).collect()
+}
+
+fn borrowed(labels: &Labels) -> Vec<(&str, &str)> {
+    labels.iter().map(|(k, v)| (*k, v.as_str())).collect()
+}
+
+struct Histogram {
+    /// Observations per bucket (not cumulative); the last slot is `+Inf`
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let slot = LATENCY_BUCKETS.iter().position(|le| value <= *le).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[slot] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Recorded {
+    counters: BTreeMap<&'static str, BTreeMap<Labels, f64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<Labels, Histogram>>,
+}
+
+/// Counters and histograms recorded since startup
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Recorded>,
+}
+
+impl Metrics {
+    pub fn inc(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        self.add(name, labels, 1.0);
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(name).or_default().entry(owned(labels)).or_default() += value;
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .histograms
+            .entry(name)
+            .or_default()
+            .entry(owned(labels))
+            .or_insert_with(Histogram::new)
+            .observe(value);
+    }
+
+    pub fn encode(&self, encoder: &mut Encoder) {
+        let inner = self.inner.lock().unwrap();
+        for (name, series) in &inner.counters {
+            encoder.family(name, "counter", help(name));
+            for (labels, value) in series {
+                encoder.sample(name, &borrowed(labels), *value);
+            }
+        }
+        for (name, series) in &inner.histograms {
+            encoder.family(name, "histogram", help(name));
+            let bucket_name = format!("{}_bucket", name);
+            for (labels, histogram) in series {
+                let mut cumulative = 0;
+                for (i, count) in histogram.buckets.iter().enumerate() {
+                    cumulative += count;
+                    let le = LATENCY_BUCKETS.get(i).map(|le| le.to_string()).unwrap_or_else(|| "+Inf".to_string());
+                    let mut bucket_labels = borrowed(labels);
+                    bucket_labels.push(("le", &le));
+                    encoder.sample(&bucket_name, &bucket_labels, cumulative as f64);
+                }
+                encoder.sample(&format!("{}_sum", name), &borrowed(labels), histogram.sum);
+                encoder.sample(&format!("{}_count", name), &borrowed(labels), histogram.count as f64);
+            }
+        }
+    }
+}
+
+/// The process-wide registry
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+/// Records one inference run; `usage` is None when it failed
+pub fn record_inference(outcome: &str, usage: Option<&Usage>) {
+    let metrics = global();
+    metrics.inc(AI_REQUESTS, &[("outcome", outcome)]);
+    if let Some(usage) = usage {
+        metrics.add(AI_PROMPT_TOKENS, &[], usage.prompt_tokens as f64);
+        metrics.add(AI_GENERATED_TOKENS, &[], usage.generated_tokens as f64);
+        metrics.observe(AI_DURATION, &[], usage.total_ms as f64 / 1000.0);
+        if let Some(ms) = usage.time_to_first_token_ms {
+            metrics.observe(AI_FIRST_TOKEN, &[], ms as f64 / 1000.0);
+        }
+    }
+}
+
+/// Writes the Prometheus text exposition format
+#[derive(Default)]
+pub struct Encoder {
+    out: String,
+}
+
+impl Encoder {
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let pairs: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+            let _ = write!(self.out, "{{{}}}", pairs.join(","));
+        }
+        let _ = writeln!(self.out, " {}", format_value(value));
+    }
+
+    /// A gauge family with a single unlabelled sample
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+// ============ HTTP ============
+
+type ServerState = axum::extract::State<Arc<(AppHandle, Arc<crate::ai::AIState>)>>;
+
+/// GET /metrics
+pub async fn metrics_http_handler(axum::extract::State(state): ServerState) -> Response {
+    let (app, ai_state) = &*state;
+    let mut encoder = Encoder::default();
+
+    if let Some(registry) = app.try_state::<ProcessRegistry>() {
+        let apps = crate::app_control::list_apps(&registry).await;
+        encoder.gauge(
+            "launcher_apps_running",
+            "Apps currently running",
+            apps.iter().filter(|a| a.running).count() as f64,
+        );
+        encoder.family("launcher_app_up", "gauge", "Whether each app is running");
+        for app in &apps {
+            encoder.sample("launcher_app_up", &[("app", &app.id)], if app.running { 1.0 } else { 0.0 });
+        }
+    }
+
+    if let Some(monitor) = app.try_state::<crate::pulse::PulseMonitor>() {
+        let readings = monitor.readings();
+        encoder.family("pulse_age_seconds", "gauge", "Seconds since the account's last pulse");
+        for (account, age, _) in &readings {
+            encoder.sample("pulse_age_seconds", &[("account", account)], age.as_secs_f64());
+        }
+        let fields: [(&str, &str, fn(&crate::alerts::PulseReading) -> f64); 3] = [
+            ("pulse_balance", "Last reported balance", |r| r.balance),
+            ("pulse_equity", "Last reported equity", |r| r.equity),
+            ("pulse_drawdown", "Last reported drawdown", |r| r.drawdown),
+        ];
+        for (name, help, value) in fields {
+            encoder.family(name, "gauge", help);
+            for (account, _, reading) in &readings {
+                if let Some(reading) = reading {
+                    encoder.sample(name, &[("account", account)], value(reading));
+                }
+            }
+        }
+    }
+
+    let queue = ai_state.workers.metrics();
+    encoder.gauge("ai_queue_depth", "Inference jobs waiting for a worker", queue.queued as f64);
+    encoder.gauge("ai_queue_running", "Inference jobs running", queue.running as f64);
+    encoder.gauge("ai_queue_capacity", "Jobs the queue accepts before rejecting", queue.capacity as f64);
+    encoder.family("ai_queue_jobs_total", "counter", "Queued inference jobs by result");
+    for (result, count) in [("completed", queue.completed), ("rejected", queue.rejected), ("timed_out", queue.timed_out)] {
+        encoder.sample("ai_queue_jobs_total", &[("result", result)], count as f64);
+    }
+
+    global().encode(&mut encoder);
+
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        encoder.finish(),
+    )
+        .into_response()
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_histograms_encode() {
+        let metrics = Metrics::default();
+        metrics.inc(APP_LOG_LINES, &[("app", "dashboard"), ("type", "info")]);
+        metrics.inc(APP_LOG_LINES, &[("app", "dashboard"), ("type", "info")]);
+        metrics.observe(AI_DURATION, &[], 0.3);
+        metrics.observe(AI_DURATION, &[], 500.0);
+
+        let mut encoder = Encoder::default();
+        metrics.encode(&mut encoder);
+        let text = encoder.finish();
+
+        assert!(text.contains("# TYPE launcher_app_log_lines_total counter\n"));
+        assert!(text.contains("launcher_app_log_lines_total{app=\"dashboard\",type=\"info\"} 2\n"));
+        assert!(text.contains("ai_inference_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("ai_inference_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("ai_inference_duration_seconds_bucket{le=\"120\"} 1\n"));
+        assert!(text.contains("ai_inference_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("ai_inference_duration_seconds_sum 500.3\n"));
+        assert!(text.contains("ai_inference_duration_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        let mut encoder = Encoder::default();
+        encoder.sample("pulse_equity", &[("account", "a\"b\\c\nd")], 1.5);
+        assert_eq!(encoder.finish(), "pulse_equity{account=\"a\\\"b\\\\c\\nd\"} 1.5\n");
+    }
+}
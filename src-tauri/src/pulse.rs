@@ -0,0 +1,575 @@
+//! MT4/MT5 Account Pulse monitor
+//! Polls the pulse CSV of every configured account, tracks feed health,
+//! aggregates a portfolio view and drives risk alerts
+
+use crate::alerts::{self, AlertEngine, PulseReading};
+use crate::event_bus;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// A feed whose last pulse is older than this is reported as stale
+const STALE_AFTER: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Serialize)]
+pub struct PulsePayload {
+    pub account_id: String,
+    pub balance: String,
+    pub equity: String,
+    pub drawdown: String,
+    pub timestamp: i64,
+}
+
+/// Health of the pulse feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PulseState {
+    /// Fresh pulses are arriving
+    Live,
+    /// The file exists but its last pulse is older than `STALE_AFTER`
+    Stale,
+    /// The pulse file does not exist
+    MissingFile,
+    /// The last read could not be parsed
+    ParseError,
+}
+
+/// Snapshot returned by `pulse_status` and emitted as `pulse-status`
+#[derive(Debug, Clone, Serialize)]
+pub struct PulseStatus {
+    pub account_id: String,
+    pub state: PulseState,
+    pub path: String,
+    pub last_timestamp: i64,
+    /// Age of the last accepted pulse, from its timestamp (None if none yet)
+    pub last_pulse_age_secs: Option<f64>,
+    /// Seconds since the pulse file was last modified (None if unknown)
+    pub file_age_secs: Option<f64>,
+    pub parse_errors: u64,
+    pub last_error: Option<String>,
+}
+
+/// Result of feeding one read of the pulse file into the tracker
+pub struct PulseUpdate {
+    pub payload: Option<PulsePayload>,
+    pub reading: Option<PulseReading>,
+    pub state_changed: bool,
+}
+
+/// Tracks the last accepted pulse and the health of the feed
+pub struct PulseTracker {
+    account_id: String,
+    currency: String,
+    path: PathBuf,
+    state: PulseState,
+    started_at: SystemTime,
+    last_timestamp: i64,
+    last_mtime: Option<SystemTime>,
+    parse_errors: u64,
+    /// Modification time and content of the last read that failed to parse
+    failed_read: Option<(Option<SystemTime>, String)>,
+    last_error: Option<String>,
+    last_reading: Option<PulseReading>,
+}
+
+impl PulseTracker {
+    pub fn new(source: &PulseSource) -> Self {
+        Self {
+            account_id: source.account_id.clone(),
+            currency: source.currency.clone(),
+            path: source.path.clone(),
+            state: PulseState::MissingFile,
+            started_at: SystemTime::now(),
+            last_timestamp: 0,
+            last_mtime: None,
+            parse_errors: 0,
+            failed_read: None,
+            last_error: None,
+            last_reading: None,
+        }
+    }
+
+    /// Reads the pulse file from disk and ingests it
+    pub fn poll(&mut self) -> PulseUpdate {
+        let content = std::fs::read_to_string(&self.path).ok();
+        let mtime = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        self.ingest(content.as_deref(), mtime, SystemTime::now())
+    }
+
+    /// Ingests one read of the pulse file. `content` is None when the file is missing.
+    pub fn ingest(&mut self, content: Option<&str>, mtime: Option<SystemTime>, now: SystemTime) -> PulseUpdate {
+        let previous = self.state;
+        let mut payload = None;
+        let mut reading = None;
+
+        if mtime.is_some() {
+            self.last_mtime = mtime;
+        }
+
+        match content {
+            None => self.state = PulseState::MissingFile,
+            Some(content) => match parse_line(content) {
+                Ok((parts, timestamp)) => {
+                    self.failed_read = None;
+                    if timestamp > self.last_timestamp {
+                        payload = Some(PulsePayload {
+                            account_id: self.account_id.clone(),
+                            balance: parts[0].to_string(),
+                            equity: parts[1].to_string(),
+                            drawdown: parts[2].to_string(),
+                            timestamp,
+                        });
+                        reading = PulseReading::parse(&parts);
+                        if reading.is_some() {
+                            self.last_reading = reading;
+                        }
+                        self.last_timestamp = timestamp;
+                        self.last_error = None;
+                    }
+                    self.state = if self.pulse_age(now) > STALE_AFTER {
+                        PulseState::Stale
+                    } else {
+                        PulseState::Live
+                    };
+                }
+                Err(e) => {
+                    // The same broken file is re-read every poll; only count it once
+                    let failed_read = (mtime, content.to_string());
+                    if self.failed_read.as_ref() != Some(&failed_read) {
+                        self.parse_errors += 1;
+                        self.failed_read = Some(failed_read);
+                    }
+                    self.last_error = Some(e);
+                    self.state = PulseState::ParseError;
+                }
+            },
+        }
+
+        PulseUpdate {
+            payload,
+            reading,
+            state_changed: self.state != previous,
+        }
+    }
+
+    /// Age of the last accepted pulse by its own timestamp. Falls back to the file's modification
+    /// time when the timestamp is ahead of the local clock, and to the time since monitoring
+    /// started if no pulse arrived yet.
+    pub fn pulse_age(&self, now: SystemTime) -> Duration {
+        let stamped = (self.last_timestamp > 0)
+            .then(|| UNIX_EPOCH + Duration::from_secs(self.last_timestamp as u64))
+            .and_then(|t| now.duration_since(t).ok());
+        stamped
+            .or_else(|| self.last_mtime.and_then(|m| now.duration_since(m).ok()))
+            .unwrap_or_else(|| now.duration_since(self.started_at).unwrap_or_default())
+    }
+
+    pub fn status(&self) -> PulseStatus {
+        let now = SystemTime::now();
+        PulseStatus {
+            account_id: self.account_id.clone(),
+            state: self.state,
+            path: self.path.to_string_lossy().to_string(),
+            last_timestamp: self.last_timestamp,
+            last_pulse_age_secs: (self.last_timestamp > 0).then(|| self.pulse_age(now).as_secs_f64()),
+            file_age_secs: self
+                .last_mtime
+                .and_then(|m| now.duration_since(m).ok())
+                .map(|d| d.as_secs_f64()),
+            parse_errors: self.parse_errors,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// Splits `balance,equity,drawdown,timestamp[,...]` and parses the timestamp
+fn parse_line(content: &str) -> Result<(Vec<&str>, i64), String> {
+    let parts: Vec<&str> = content.trim().split(',').collect();
+    if parts.len() < 4 {
+        return Err(format!("Expected at least 4 fields, got {}", parts.len()));
+    }
+    let timestamp = parts[3]
+        .trim()
+        .parse::<i64>()
+        .map_err(|e| format!("Invalid timestamp '{}': {}", parts[3], e))?;
+    Ok((parts, timestamp))
+}
+
+// ============ Sources & Portfolio ============
+
+/// One account's pulse file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PulseSource {
+    pub account_id: String,
+    pub path: PathBuf,
+    /// Account deposit currency, converted to the base currency for the portfolio view
+    #[serde(default = "default_currency")]
+    pub currency: String,
+}
+
+/// Pulse sources and FX rates, stored as `pulse_sources.json` in the app data dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PulseConfig {
+    #[serde(default = "default_currency")]
+    pub base_currency: String,
+    /// Value of one unit of each currency in the base currency (e.g. "EUR": 1.08 for a USD base)
+    #[serde(default)]
+    pub fx_rates: HashMap<String, f64>,
+    pub sources: Vec<PulseSource>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl PulseConfig {
+    /// Loads the config from `path`, falling back to a single `default` account reading `default_pulse_path`
+    pub fn load(path: &std::path::Path, default_pulse_path: PathBuf) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_else(|| Self {
+                base_currency: default_currency(),
+                fx_rates: HashMap::new(),
+                sources: vec![PulseSource {
+                    account_id: "default".to_string(),
+                    path: default_pulse_path,
+                    currency: default_currency(),
+                }],
+            })
+    }
+
+    /// Conversion rate from `currency` to the base currency
+    fn rate(&self, currency: &str) -> Option<f64> {
+        if currency.eq_ignore_ascii_case(&self.base_currency) {
+            Some(1.0)
+        } else {
+            self.fx_rates
+                .iter()
+                .find(|(c, _)| c.eq_ignore_ascii_case(currency))
+                .map(|(_, rate)| *rate)
+        }
+    }
+}
+
+/// One account's contribution to the portfolio, in the base currency
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioAccount {
+    pub account_id: String,
+    pub currency: String,
+    pub state: PulseState,
+    pub balance: f64,
+    pub equity: f64,
+    pub drawdown: f64,
+}
+
+/// Aggregated pulse across all accounts, emitted as `portfolio-pulse`
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioPulse {
+    pub base_currency: String,
+    pub total_balance: f64,
+    pub total_equity: f64,
+    /// Combined floating drawdown in percent of the total balance
+    pub drawdown: f64,
+    pub accounts: Vec<PortfolioAccount>,
+    /// Accounts left out because they have no pulse yet or no FX rate
+    pub excluded: Vec<String>,
+    pub timestamp: i64, // Unix timestamp
+}
+
+fn aggregate(config: &PulseConfig, trackers: &BTreeMap<String, PulseTracker>) -> PortfolioPulse {
+    let mut accounts = Vec::new();
+    let mut excluded = Vec::new();
+
+    for tracker in trackers.values() {
+        match (tracker.last_reading, config.rate(&tracker.currency)) {
+            (Some(reading), Some(rate)) => accounts.push(PortfolioAccount {
+                account_id: tracker.account_id.clone(),
+                currency: tracker.currency.clone(),
+                state: tracker.state,
+                balance: reading.balance * rate,
+                equity: reading.equity * rate,
+                drawdown: reading.drawdown,
+            }),
+            _ => excluded.push(tracker.account_id.clone()),
+        }
+    }
+
+    let total_balance: f64 = accounts.iter().map(|a| a.balance).sum();
+    let total_equity: f64 = accounts.iter().map(|a| a.equity).sum();
+    let drawdown = if total_balance > 0.0 {
+        ((total_balance - total_equity) / total_balance * 100.0).max(0.0)
+    } else {
+        0.0
+    };
+
+    PortfolioPulse {
+        base_currency: config.base_currency.clone(),
+        total_balance,
+        total_equity,
+        drawdown,
+        accounts,
+        excluded,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+    }
+}
+
+struct MonitorInner {
+    config: PulseConfig,
+    trackers: BTreeMap<String, PulseTracker>,
+}
+
+fn build_trackers(config: &PulseConfig) -> BTreeMap<String, PulseTracker> {
+    config
+        .sources
+        .iter()
+        .map(|source| (source.account_id.clone(), PulseTracker::new(source)))
+        .collect()
+}
+
+/// Managed state shared between the monitor loop and the pulse commands
+pub struct PulseMonitor {
+    config_path: PathBuf,
+    inner: Mutex<MonitorInner>,
+}
+
+impl PulseMonitor {
+    pub fn new(config_path: PathBuf, config: PulseConfig) -> Self {
+        Self {
+            config_path,
+            inner: Mutex::new(MonitorInner {
+                trackers: build_trackers(&config),
+                config,
+            }),
+        }
+    }
+
+    /// Folder holding the account's pulse CSV (the EA's `MQL4/Files` / `MQL5/Files`)
+    pub fn files_dir(&self, account_id: &str) -> Option<PathBuf> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .config
+            .sources
+            .iter()
+            .find(|s| s.account_id == account_id)
+            .and_then(|s| s.path.parent().map(|p| p.to_path_buf()))
+    }
+
+    /// Feed status of every tracked account
+    pub fn statuses(&self) -> Vec<PulseStatus> {
+        self.inner.lock().unwrap().trackers.values().map(|t| t.status()).collect()
+    }
+
+    /// Pulse age and last numeric reading of every tracked account
+    pub fn readings(&self) -> Vec<(String, Duration, Option<PulseReading>)> {
+        let now = SystemTime::now();
+        let inner = self.inner.lock().unwrap();
+        let mut readings: Vec<_> = inner
+            .trackers
+            .iter()
+            .map(|(id, t)| (id.clone(), t.pulse_age(now), t.last_reading))
+            .collect();
+        readings.sort_by(|a, b| a.0.cmp(&b.0));
+        readings
+    }
+
+    /// Replaces the configuration, restarting tracking for every account
+    pub fn set_config(&self, config: PulseConfig) -> Result<(), String> {
+        let mut ids: Vec<&str> = config.sources.iter().map(|s| s.account_id.as_str()).collect();
+        ids.sort_unstable();
+        if ids.windows(2).any(|w| w[0] == w[1]) {
+            return Err("Pulse account ids must be unique".to_string());
+        }
+
+        if let Some(parent) = self.config_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+        std::fs::write(&self.config_path, json)
+            .map_err(|e| format!("Failed to write {:?}: {}", self.config_path, e))?;
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.trackers = build_trackers(&config);
+        inner.config = config;
+        Ok(())
+    }
+}
+
+/// Polls every pulse file once per second until the app exits
+pub async fn run_monitor<R: Runtime>(app: AppHandle<R>) {
+    let monitor = app.state::<PulseMonitor>();
+    let alert_engine = app.state::<AlertEngine>();
+    let mut interval = tokio::time::interval(Duration::from_millis(1000));
+
+    loop {
+        interval.tick().await;
+
+        let mut updates = Vec::new();
+        let portfolio = {
+            let mut inner = monitor.inner.lock().unwrap();
+            let now = SystemTime::now();
+            let mut any_pulse = false;
+            for (account_id, tracker) in inner.trackers.iter_mut() {
+                let update = tracker.poll();
+                any_pulse |= update.payload.is_some();
+                updates.push((account_id.clone(), update, tracker.status(), tracker.pulse_age(now)));
+            }
+            any_pulse.then(|| aggregate(&inner.config, &inner.trackers))
+        };
+
+        for (account_id, update, status, pulse_age) in updates {
+            if let Some(payload) = &update.payload {
+                event_bus::emit(&app, &format!("pulse:{}", account_id), "account-pulse", payload);
+            }
+            if update.state_changed {
+                event_bus::emit(&app, &format!("pulse:{}", account_id), "pulse-status", &status);
+            }
+            for alert in alert_engine.evaluate(&account_id, update.reading, pulse_age) {
+                alerts::dispatch(&app, &alert);
+            }
+        }
+
+        if let Some(portfolio) = portfolio {
+            event_bus::emit(&app, "portfolio", "portfolio-pulse", &portfolio);
+        }
+    }
+}
+
+// ============ Commands ============
+
+/// Returns the feed status of one account, or of every account when `account_id` is omitted
+#[tauri::command]
+pub fn pulse_status(
+    account_id: Option<String>,
+    monitor: tauri::State<'_, PulseMonitor>,
+) -> Result<Vec<PulseStatus>, String> {
+    let inner = monitor.inner.lock().unwrap();
+    match account_id {
+        Some(id) => inner
+            .trackers
+            .get(&id)
+            .map(|t| vec![t.status()])
+            .ok_or_else(|| format!("Unknown pulse account: {}", id)),
+        None => Ok(inner.trackers.values().map(|t| t.status()).collect()),
+    }
+}
+
+#[tauri::command]
+pub fn get_portfolio_pulse(monitor: tauri::State<'_, PulseMonitor>) -> PortfolioPulse {
+    let inner = monitor.inner.lock().unwrap();
+    aggregate(&inner.config, &inner.trackers)
+}
+
+#[tauri::command]
+pub fn get_pulse_config(monitor: tauri::State<'_, PulseMonitor>) -> PulseConfig {
+    monitor.inner.lock().unwrap().config.clone()
+}
+
+#[tauri::command]
+pub fn set_pulse_config(config: PulseConfig, monitor: tauri::State<'_, PulseMonitor>) -> Result<(), String> {
+    monitor.set_config(config)
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_states() {
+        let mut tracker = PulseTracker::new(&PulseSource {
+            account_id: "demo".into(),
+            path: PathBuf::from("pulse.csv"),
+            currency: "USD".into(),
+        });
+        let start = UNIX_EPOCH + Duration::from_secs(1700000000);
+
+        let update = tracker.ingest(None, None, start);
+        assert!(update.payload.is_none());
+        assert_eq!(tracker.state, PulseState::MissingFile);
+
+        let update = tracker.ingest(Some("10000,9950,0.5,1700000000\n"), None, start);
+        assert!(update.payload.is_some());
+        assert!(update.state_changed);
+        assert_eq!(tracker.state, PulseState::Live);
+
+        // Same timestamp again is not a new pulse
+        let update = tracker.ingest(Some("10000,9950,0.5,1700000000"), None, start + Duration::from_secs(5));
+        assert!(update.payload.is_none());
+        assert!(!update.state_changed);
+
+        let update = tracker.ingest(Some("10000,9950,0.5,1700000000"), None, start + Duration::from_secs(20));
+        assert!(update.state_changed);
+        assert_eq!(tracker.state, PulseState::Stale);
+
+        tracker.ingest(Some("10000,9950"), None, start + Duration::from_secs(21));
+        assert_eq!(tracker.state, PulseState::ParseError);
+        assert_eq!(tracker.parse_errors, 1);
+
+        // Polling the same broken file again is not a new error, a rewrite is
+        tracker.ingest(Some("10000,9950"), None, start + Duration::from_secs(22));
+        tracker.ingest(Some("10000,9950"), None, start + Duration::from_secs(23));
+        assert_eq!(tracker.parse_errors, 1);
+        tracker.ingest(Some("10000,9950"), Some(start + Duration::from_secs(23)), start + Duration::from_secs(23));
+        tracker.ingest(Some("10000,x"), Some(start + Duration::from_secs(23)), start + Duration::from_secs(24));
+        assert_eq!(tracker.parse_errors, 3);
+
+        let update = tracker.ingest(Some("10010,9990,0.2,1700000030"), None, start + Duration::from_secs(25));
+        assert!(update.payload.is_some());
+        assert_eq!(tracker.state, PulseState::Live);
+    }
+
+    #[test]
+    fn test_old_pulse_on_first_read() {
+        let mut tracker = PulseTracker::new(&PulseSource {
+            account_id: "demo".into(),
+            path: PathBuf::from("pulse.csv"),
+            currency: "USD".into(),
+        });
+        let now = UNIX_EPOCH + Duration::from_secs(1700003600);
+
+        // Left over from a terminal that stopped an hour ago
+        let update = tracker.ingest(Some("10000,9950,0.5,1700000000"), Some(now), now);
+        assert!(update.payload.is_some());
+        assert_eq!(tracker.state, PulseState::Stale);
+        assert_eq!(tracker.pulse_age(now), Duration::from_secs(3600));
+
+        // A timestamp ahead of the local clock falls back to the file's modification time
+        let update = tracker.ingest(Some("10000,9950,0.5,1700007200"), Some(now - Duration::from_secs(2)), now);
+        assert!(update.payload.is_some());
+        assert_eq!(tracker.state, PulseState::Live);
+        assert_eq!(tracker.pulse_age(now), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_portfolio_aggregation() {
+        let config = PulseConfig {
+            base_currency: "USD".into(),
+            fx_rates: HashMap::from([("EUR".to_string(), 1.10)]),
+            sources: vec![
+                PulseSource { account_id: "usd".into(), path: PathBuf::from("a.csv"), currency: "USD".into() },
+                PulseSource { account_id: "eur".into(), path: PathBuf::from("b.csv"), currency: "eur".into() },
+                PulseSource { account_id: "jpy".into(), path: PathBuf::from("c.csv"), currency: "JPY".into() },
+            ],
+        };
+        let mut trackers = build_trackers(&config);
+        let now = SystemTime::now();
+        trackers.get_mut("usd").unwrap().ingest(Some("10000,9000,10,1700000000"), None, now);
+        trackers.get_mut("eur").unwrap().ingest(Some("10000,10000,0,1700000000"), None, now);
+        trackers.get_mut("jpy").unwrap().ingest(Some("1000000,990000,1,1700000000"), None, now);
+
+        let portfolio = aggregate(&config, &trackers);
+        assert_eq!(portfolio.accounts.len(), 2);
+        assert_eq!(portfolio.excluded, vec!["jpy".to_string()]);
+        assert!((portfolio.total_balance - 21_000.0).abs() < 1e-6);
+        assert!((portfolio.total_equity - 20_000.0).abs() < 1e-6);
+        assert!((portfolio.drawdown - 1000.0 / 21_000.0 * 100.0).abs() < 1e-9);
+    }
+}
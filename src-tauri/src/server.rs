@@ -0,0 +1,547 @@
+//! Embedded HTTP server security
+//! The server listens on 127.0.0.1 unless LAN mode is enabled in `server.json`. Every request
+//! needs the bearer token from the `api_token` file in the app data dir (created on first run and
+//! read by the other ecosystem apps), CORS only admits the apps' own origins and each client IP
+//! is rate limited.
+//! Binding falls back to the next free port when the configured one is taken; the address in use
+//! is reported through `service-status` and the `api_endpoint` file.
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Well-known file in the app data dir holding the API token
+pub const TOKEN_FILE: &str = "api_token";
+/// Well-known file in the app data dir holding the URL the server actually listens on
+pub const ENDPOINT_FILE: &str = "api_endpoint";
+/// How long in-flight requests get to finish when the app exits
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Extra attempts on the configured port, e.g. while a previous instance is still shutting down
+const BIND_RETRIES: u32 = 2;
+const BIND_RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Buckets idle this long are forgotten
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Listen on all interfaces instead of loopback only
+    #[serde(default)]
+    pub allow_lan: bool,
+    /// Explicit listen address; overrides `allow_lan`
+    #[serde(default)]
+    pub address: Option<IpAddr>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// How many ports above `port` to try when it is taken
+    #[serde(default = "default_port_fallbacks")]
+    pub port_fallbacks: u16,
+    /// Sustained requests per minute per client
+    #[serde(default = "default_rate_limit_per_minute")]
+    pub rate_limit_per_minute: u32,
+    /// Requests a client may fire in a burst before the limit kicks in
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_port() -> u16 {
+    3030
+}
+
+fn default_port_fallbacks() -> u16 {
+    10
+}
+
+fn default_rate_limit_per_minute() -> u32 {
+    120
+}
+
+fn default_rate_limit_burst() -> u32 {
+    30
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            allow_lan: false,
+            address: None,
+            port: default_port(),
+            port_fallbacks: default_port_fallbacks(),
+            rate_limit_per_minute: default_rate_limit_per_minute(),
+            rate_limit_burst: default_rate_limit_burst(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn bind_ip(&self) -> IpAddr {
+        if let Some(address) = self.address {
+            address
+        } else if self.allow_lan {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        }
+    }
+}
+
+/// Reads the API token, generating and saving a new one on first run
+pub fn load_or_create_token(path: &Path) -> Result<String, String> {
+    if let Ok(existing) = std::fs::read_to_string(path) {
+        let existing = existing.trim();
+        if existing.len() >= 32 {
+            return Ok(existing.to_string());
+        }
+    }
+
+    let bytes: [u8; 32] = rand::random();
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+
+    // Owner-only from the moment the file exists, so the token is never readable by others
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    #[cfg(unix)]
+    {
+        // `mode` only applies to new files; tighten a short/corrupt leftover before overwriting it
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict {:?}: {}", path, e))?;
+    }
+    std::io::Write::write_all(&mut file, token.as_bytes()).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(token)
+}
+
+/// Compares in constant time so the token can't be guessed byte by byte from response timings
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    let (given, expected) = (given.as_bytes(), expected.as_bytes());
+    if given.len() != expected.len() {
+        return false;
+    }
+    given.iter().zip(expected).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Origins of the ecosystem apps: their Vite dev servers and the packaged Tauri webviews
+pub fn allowed_origins() -> Vec<String> {
+    let mut origins = vec![
+        "tauri://localhost".to_string(),
+        "http://tauri.localhost".to_string(),
+        "https://tauri.localhost".to_string(),
+    ];
+    let mut ports: Vec<u16> = crate::APP_IDS.iter().filter_map(|id| crate::get_app_port(id)).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    for port in ports {
+        origins.push(format!("http://localhost:{}", port));
+        origins.push(format!("http://127.0.0.1:{}", port));
+    }
+    origins
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client IP
+pub struct RateLimiter {
+    capacity: f64,
+    per_sec: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            capacity: burst.max(1) as f64,
+            per_sec: per_minute.max(1) as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes one request from `ip`'s bucket, or returns how long until the next one is allowed
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > 1024 {
+            buckets.retain(|_, b| now.duration_since(b.updated) < BUCKET_IDLE);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_sec))
+        }
+    }
+}
+
+/// Shared by the auth/rate-limit middleware
+pub struct ApiGuard {
+    token: String,
+    limiter: RateLimiter,
+}
+
+impl ApiGuard {
+    pub fn new(token: String, config: &ServerConfig) -> Self {
+        Self {
+            token,
+            limiter: RateLimiter::new(config.rate_limit_per_minute, config.rate_limit_burst),
+        }
+    }
+}
+
+/// Binds the configured port, retrying briefly, then the fallback ports above it
+pub async fn bind_listener(ip: IpAddr, port: u16, fallbacks: u16) -> Result<TcpListener, String> {
+    let mut last_error = None;
+    for attempt in 0..=BIND_RETRIES {
+        if attempt > 0 {
+            tokio::time::sleep(BIND_RETRY_DELAY).await;
+        }
+        match TcpListener::bind(SocketAddr::new(ip, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    for candidate in (1..=fallbacks).filter_map(|offset| port.checked_add(offset)) {
+        match TcpListener::bind(SocketAddr::new(ip, candidate)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(format!(
+        "Could not bind {} on ports {}-{}: {}",
+        ip,
+        port,
+        port.saturating_add(fallbacks),
+        last_error.map(|e| e.to_string()).unwrap_or_default()
+    ))
+}
+
+/// URL other apps should use; a wildcard bind is still reachable on loopback
+fn advertised_url(addr: SocketAddr) -> String {
+    let ip = if addr.ip().is_unspecified() { IpAddr::V4(Ipv4Addr::LOCALHOST) } else { addr.ip() };
+    format!("http://{}", SocketAddr::new(ip, addr.port()))
+}
+
+// ============ Lifecycle ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    /// Turned off in `server.json`
+    Disabled,
+    Starting,
+    Running,
+    /// No port could be bound or the server stopped with an error
+    Failed,
+    /// Draining in-flight requests
+    Stopping,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceStatus {
+    pub state: ServiceState,
+    pub requested_port: u16,
+    /// Port actually bound; differs from `requested_port` when that one was taken
+    pub port: Option<u16>,
+    pub url: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Owns the server task so it can be stopped and drained on exit
+pub struct ServerHandle {
+    config: ServerConfig,
+    endpoint_path: PathBuf,
+    status: Mutex<ServiceStatus>,
+    shutdown: watch::Sender<bool>,
+    task: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+}
+
+impl ServerHandle {
+    pub fn new(config: ServerConfig, endpoint_path: PathBuf) -> Self {
+        let status = ServiceStatus {
+            state: if config.enabled { ServiceState::Starting } else { ServiceState::Disabled },
+            requested_port: config.port,
+            port: None,
+            url: None,
+            error: None,
+        };
+        Self {
+            config,
+            endpoint_path,
+            status: Mutex::new(status),
+            shutdown: watch::channel(false).0,
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn update<R: Runtime>(&self, app: &AppHandle<R>, change: impl FnOnce(&mut ServiceStatus)) {
+        let status = {
+            let mut status = self.status.lock().unwrap();
+            change(&mut status);
+            status.clone()
+        };
+        crate::event_bus::emit(app, "services", "service-status", &status);
+    }
+
+    /// Turns true when `shutdown` starts; long-lived connections watch it to close early
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Stops accepting connections and waits up to `timeout` for in-flight requests
+    pub fn shutdown(&self, timeout: Duration) {
+        self.shutdown.send_replace(true);
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let drained = tauri::async_runtime::block_on(tokio::time::timeout(timeout, task));
+            if drained.is_err() {
+                eprintln!("AI server did not drain within {:?}", timeout);
+            }
+        }
+        let _ = std::fs::remove_file(&self.endpoint_path);
+    }
+}
+
+/// Starts serving `router` in the background unless the server is disabled
+pub fn spawn<R: Runtime>(app: AppHandle<R>, router: Router) {
+    let server = app.state::<ServerHandle>();
+    if !server.config.enabled {
+        println!("AI server disabled in server.json");
+        server.update(&app, |status| status.state = ServiceState::Disabled);
+        return;
+    }
+
+    let mut shutdown = server.shutdown.subscribe();
+    let task_app = app.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        let server = task_app.state::<ServerHandle>();
+        let config = &server.config;
+        let listener = match bind_listener(config.bind_ip(), config.port, config.port_fallbacks).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("AI server failed to start: {}", e);
+                server.update(&task_app, |status| {
+                    status.state = ServiceState::Failed;
+                    status.error = Some(e);
+                });
+                return;
+            }
+        };
+
+        let addr = match listener.local_addr() {
+            Ok(addr) => addr,
+            Err(e) => {
+                server.update(&task_app, |status| {
+                    status.state = ServiceState::Failed;
+                    status.error = Some(e.to_string());
+                });
+                return;
+            }
+        };
+        let url = advertised_url(addr);
+        if let Err(e) = std::fs::write(&server.endpoint_path, &url) {
+            eprintln!("Failed to write {:?}: {}", server.endpoint_path, e);
+        }
+        println!("AI singleton server listening on {}", url);
+        server.update(&task_app, |status| {
+            status.state = ServiceState::Running;
+            status.port = Some(addr.port());
+            status.url = Some(url.clone());
+            status.error = None;
+        });
+
+        let draining_app = task_app.clone();
+        let result = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move {
+                let _ = shutdown.wait_for(|stop| *stop).await;
+                draining_app.state::<ServerHandle>().update(&draining_app, |status| {
+                    status.state = ServiceState::Stopping;
+                });
+            })
+            .await;
+
+        server.update(&task_app, |status| match result {
+            Ok(()) => status.state = ServiceState::Stopped,
+            Err(e) => {
+                status.state = ServiceState::Failed;
+                status.error = Some(e.to_string());
+            }
+        });
+    });
+    *server.task.lock().unwrap() = Some(task);
+}
+
+// ============ Middleware ============
+
+/// Browsers can't set headers on a WebSocket handshake, so upgrades may pass `?access_token=` instead
+fn query_token(request: &Request) -> Option<&str> {
+    let upgrade = request.headers().get(header::UPGRADE).and_then(|v| v.to_str().ok())?;
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return None;
+    }
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("access_token="))
+}
+
+/// Rejects clients over their rate limit (429) and requests without the bearer token (401)
+pub async fn guard_middleware(
+    State(guard): State<Arc<ApiGuard>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = guard.limiter.check(addr.ip(), Instant::now()) {
+        let secs = retry_after.as_secs().max(1).to_string();
+        return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, secs)], "Rate limit exceeded").into_response();
+    }
+
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| query_token(&request))
+        .is_some_and(|given| token_matches(given.trim(), &guard.token));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid API token",
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+pub fn cors_layer() -> CorsLayer {
+    let origins: Vec<HeaderValue> = allowed_origins().iter().filter_map(|o| o.parse().ok()).collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+}
+
+// ============ Commands ============
+
+/// Current state and address of the embedded server
+#[tauri::command]
+pub fn service_status(server: tauri::State<'_, ServerHandle>) -> ServiceStatus {
+    server.status()
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let limiter = RateLimiter::new(60, 2);
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "192.168.1.20".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check(ip, start).is_ok());
+        assert!(limiter.check(ip, start).is_ok());
+        let retry = limiter.check(ip, start).unwrap_err();
+        assert!(retry <= Duration::from_secs(1));
+        assert!(limiter.check(other, start).is_ok(), "buckets are per client");
+
+        assert!(limiter.check(ip, start + Duration::from_millis(1100)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_millis(1100)).is_err());
+    }
+
+    #[test]
+    fn test_token_file_and_compare() {
+        let path = std::env::temp_dir().join("daavfx_server_test").join(TOKEN_FILE);
+        let _ = std::fs::remove_file(&path);
+
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(load_or_create_token(&path).unwrap(), token, "token survives restarts");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "token file is owner-only");
+        }
+
+        assert!(token_matches(&token, &token));
+        assert!(!token_matches(&token[..63], &token));
+        assert!(!token_matches(&token.replace(&token[..1], "-"), &token));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_bind_falls_back_when_port_taken() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let listener = bind_listener(IpAddr::V4(Ipv4Addr::LOCALHOST), port, 20).await.unwrap();
+        let bound = listener.local_addr().unwrap().port();
+        assert_ne!(bound, port);
+        assert!(bound > port && bound <= port + 20);
+
+        assert!(bind_listener(IpAddr::V4(Ipv4Addr::LOCALHOST), port, 0).await.is_err());
+
+        let config: ServerConfig = serde_json::from_str(r#"{"allow_lan": true}"#).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.port, 3030);
+        assert_eq!(config.bind_ip(), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(advertised_url(SocketAddr::new(config.bind_ip(), 3031)), "http://127.0.0.1:3031");
+    }
+}
@@ -0,0 +1,246 @@
+//! Local service discovery
+//! Lists the launcher's own API, every app the launcher started and the pulse feeds, with health,
+//! version and URL, so sibling apps can look each other up instead of hard-coding ports.
+
+use crate::pulse::{PulseMonitor, PulseState, PulseStatus};
+use crate::server::{ServerHandle, ServiceState, ServiceStatus};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::net::TcpStream;
+use tokio::process::Child;
+use tokio::sync::Mutex;
+
+/// How long a dev server port gets to accept a probe connection
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceKind {
+    /// The launcher's HTTP API
+    Api,
+    /// An app's Vite dev server
+    DevServer,
+    /// A tracked process without a port, e.g. an MT4/MT5 terminal
+    Process,
+    /// An account pulse feed
+    Feed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Healthy,
+    /// Running but not answering yet
+    Starting,
+    /// Answering but not fully working, e.g. a stale pulse feed
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceInfo {
+    pub id: String,
+    pub kind: ServiceKind,
+    pub health: Health,
+    pub url: Option<String>,
+    pub port: Option<u16>,
+    pub version: Option<String>,
+    pub pid: Option<u32>,
+    /// Why the service is unhealthy, or what a feed reads
+    pub detail: Option<String>,
+}
+
+impl ServiceInfo {
+    fn new(id: impl Into<String>, kind: ServiceKind, health: Health) -> Self {
+        Self {
+            id: id.into(),
+            kind,
+            health,
+            url: None,
+            port: None,
+            version: None,
+            pid: None,
+            detail: None,
+        }
+    }
+}
+
+fn api_service(status: &ServiceStatus, version: &str) -> ServiceInfo {
+    let health = match status.state {
+        ServiceState::Running => Health::Healthy,
+        ServiceState::Starting => Health::Starting,
+        ServiceState::Stopping => Health::Degraded,
+        ServiceState::Disabled | ServiceState::Failed | ServiceState::Stopped => Health::Down,
+    };
+    let mut info = ServiceInfo::new("launcher-api", ServiceKind::Api, health);
+    info.url = status.url.clone();
+    info.port = status.port;
+    info.version = Some(version.to_string());
+    info.pid = Some(std::process::id());
+    info.detail = match status.state {
+        ServiceState::Disabled => Some("Disabled in server.json".to_string()),
+        _ => status.error.clone(),
+    };
+    info
+}
+
+fn pulse_service(status: &PulseStatus) -> ServiceInfo {
+    let health = match status.state {
+        PulseState::Live => Health::Healthy,
+        PulseState::Stale => Health::Degraded,
+        PulseState::MissingFile | PulseState::ParseError => Health::Down,
+    };
+    let mut info = ServiceInfo::new(format!("pulse:{}", status.account_id), ServiceKind::Feed, health);
+    info.detail = Some(match &status.last_error {
+        Some(error) if status.state == PulseState::ParseError => format!("{}: {}", status.path, error),
+        _ => status.path.clone(),
+    });
+    info
+}
+
+/// `version` from the app's package.json
+fn read_package_version(app_dir: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(app_dir.join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&content).ok()?;
+    package.get("version")?.as_str().map(|v| v.to_string())
+}
+
+async fn port_open(port: u16) -> bool {
+    matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(("127.0.0.1", port))).await,
+        Ok(Ok(_))
+    )
+}
+
+async fn process_service(app_id: &str, child: &Arc<Mutex<Child>>, apps_root: Option<&Path>) -> ServiceInfo {
+    let port = crate::get_app_port(app_id);
+    let kind = if port.is_some() { ServiceKind::DevServer } else { ServiceKind::Process };
+    let mut info = ServiceInfo::new(app_id, kind, Health::Healthy);
+
+    // Launch/kill hold the lock only briefly; treat a busy lock as still running
+    let exited = match child.try_lock() {
+        Ok(mut child) => {
+            info.pid = child.id();
+            child.try_wait().ok().flatten()
+        }
+        Err(_) => None,
+    };
+
+    if let Some(status) = exited {
+        info.health = Health::Down;
+        info.detail = Some(format!("Exited with {}", status));
+    } else if let Some(port) = port {
+        info.port = Some(port);
+        info.url = Some(format!("http://localhost:{}", port));
+        if !port_open(port).await {
+            info.health = Health::Starting;
+            info.detail = Some(format!("Port {} is not accepting connections yet", port));
+        }
+    }
+
+    info.version = crate::get_app_dir(app_id)
+        .zip(apps_root)
+        .and_then(|(dir, root)| read_package_version(&root.join(dir)));
+    info
+}
+
+/// Snapshot of every service the launcher knows about
+pub async fn discover<R: Runtime>(app: &AppHandle<R>) -> Vec<ServiceInfo> {
+    let mut services = Vec::new();
+
+    if let Some(server) = app.try_state::<ServerHandle>() {
+        services.push(api_service(&server.status(), &app.package_info().version.to_string()));
+    }
+
+    let apps_root = crate::get_apps_base_path(app).ok();
+    if let Some(registry) = app.try_state::<crate::ProcessRegistry>() {
+        let mut children: Vec<(String, Arc<Mutex<Child>>)> = registry
+            .children
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        children.sort_by(|a, b| a.0.cmp(&b.0));
+        for (app_id, child) in children {
+            services.push(process_service(&app_id, &child, apps_root.as_deref()).await);
+        }
+    }
+
+    if let Some(monitor) = app.try_state::<PulseMonitor>() {
+        let mut feeds = monitor.statuses();
+        feeds.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        services.extend(feeds.iter().map(pulse_service));
+    }
+
+    services
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub async fn list_services<R: Runtime>(app: AppHandle<R>) -> Result<Vec<ServiceInfo>, String> {
+    Ok(discover(&app).await)
+}
+
+// ============ HTTP ============
+
+/// GET /services
+pub async fn services_http_handler(
+    axum::extract::State(state): axum::extract::State<Arc<(AppHandle, Arc<crate::ai::AIState>)>>,
+) -> axum::Json<Vec<ServiceInfo>> {
+    axum::Json(discover(&state.0).await)
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_and_pulse_health() {
+        let mut status = ServiceStatus {
+            state: ServiceState::Running,
+            requested_port: 3030,
+            port: Some(3031),
+            url: Some("http://127.0.0.1:3031".to_string()),
+            error: None,
+        };
+        let info = api_service(&status, "1.0.0");
+        assert_eq!(info.health, Health::Healthy);
+        assert_eq!(info.port, Some(3031));
+        assert_eq!(info.url.as_deref(), Some("http://127.0.0.1:3031"));
+
+        status.state = ServiceState::Failed;
+        status.error = Some("Could not bind".to_string());
+        let info = api_service(&status, "1.0.0");
+        assert_eq!(info.health, Health::Down);
+        assert_eq!(info.detail.as_deref(), Some("Could not bind"));
+
+        let feed = PulseStatus {
+            account_id: "live".to_string(),
+            state: PulseState::Stale,
+            path: "Ryiuk_AccountPulse.csv".to_string(),
+            last_timestamp: 0,
+            last_pulse_age_secs: Some(90.0),
+            file_age_secs: None,
+            parse_errors: 0,
+            last_error: None,
+        };
+        let info = pulse_service(&feed);
+        assert_eq!(info.id, "pulse:live");
+        assert_eq!(info.health, Health::Degraded);
+    }
+
+    #[test]
+    fn test_read_package_version() {
+        let dir = std::env::temp_dir().join("daavfx_services_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("package.json"), r#"{"name": "dashboard", "version": "0.3.1"}"#).unwrap();
+        assert_eq!(read_package_version(&dir).as_deref(), Some("0.3.1"));
+        assert_eq!(read_package_version(&dir.join("missing")), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,340 @@
+//! MT4/MT5 terminal discovery
+//! Scans standard install locations and Wine prefixes, maps data folders back to
+//! their installation through `origin.txt` and reports the MQL directories of each terminal
+
+use crate::terminals::{TerminalKind, TerminalStore};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A terminal data folder found on this machine
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedTerminal {
+    pub kind: TerminalKind,
+    /// Install folder name, e.g. "IC Markets MetaTrader 4"
+    pub name: String,
+    pub install_path: Option<PathBuf>,
+    pub executable: Option<PathBuf>,
+    pub data_path: PathBuf,
+    /// `MQL4/Files` or `MQL5/Files` - where the EA writes the pulse CSV
+    pub files_path: PathBuf,
+    pub experts_path: PathBuf,
+    pub presets_path: PathBuf,
+    /// Experts log (`MQL4/Logs`)
+    pub experts_logs_path: PathBuf,
+    /// Terminal journal (`logs`)
+    pub journal_path: PathBuf,
+    /// Data folder is the install folder (`/portable` mode)
+    pub portable: bool,
+    pub wine_prefix: Option<PathBuf>,
+}
+
+impl DetectedTerminal {
+    fn new(kind: TerminalKind, data_path: PathBuf, portable: bool, wine_prefix: Option<PathBuf>) -> Self {
+        let mql = data_path.join(match kind {
+            TerminalKind::Mt4 => "MQL4",
+            TerminalKind::Mt5 => "MQL5",
+        });
+        Self {
+            kind,
+            name: folder_name(&data_path),
+            install_path: None,
+            executable: None,
+            files_path: mql.join("Files"),
+            experts_path: mql.join("Experts"),
+            presets_path: mql.join("Presets"),
+            experts_logs_path: mql.join("Logs"),
+            journal_path: data_path.join("logs"),
+            data_path,
+            portable,
+            wine_prefix,
+        }
+    }
+}
+
+/// A `terminal.exe` / `terminal64.exe` installation
+#[derive(Debug, Clone)]
+struct Installation {
+    kind: TerminalKind,
+    path: PathBuf,
+    executable: PathBuf,
+}
+
+/// Where to look: Program Files style folders and `MetaQuotes/Terminal` data roots
+#[derive(Debug, Default)]
+struct ScanRoot {
+    program_dirs: Vec<PathBuf>,
+    data_roots: Vec<PathBuf>,
+    wine_prefix: Option<PathBuf>,
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn list_dirs(path: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Decodes a text file written by MetaTrader: UTF-16LE with BOM, or UTF-8/ANSI otherwise
+pub fn decode_text(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+/// Maps a Windows path from `origin.txt` to a host path
+fn host_path(windows_path: &str, wine_prefix: Option<&Path>) -> PathBuf {
+    let windows_path = windows_path.trim().trim_end_matches('\\');
+    let Some(prefix) = wine_prefix else {
+        return PathBuf::from(windows_path);
+    };
+
+    let (drive, rest) = windows_path.split_at(windows_path.find(':').map(|i| i + 1).unwrap_or(0));
+    let base = if drive.eq_ignore_ascii_case("z:") {
+        PathBuf::from("/")
+    } else {
+        prefix.join(format!("drive_{}", drive.trim_end_matches(':').to_ascii_lowercase()))
+    };
+    rest.split('\\').filter(|s| !s.is_empty()).fold(base, |acc, part| acc.join(part))
+}
+
+fn scan_wine_prefix(prefix: &Path) -> ScanRoot {
+    let drive_c = prefix.join("drive_c");
+    let program_dirs = list_dirs(&drive_c)
+        .into_iter()
+        .filter(|p| folder_name(p).starts_with("Program Files"))
+        .collect();
+
+    let mut data_roots = Vec::new();
+    for user in list_dirs(&drive_c.join("users")) {
+        data_roots.push(user.join("AppData").join("Roaming").join("MetaQuotes").join("Terminal"));
+        data_roots.push(user.join("Application Data").join("MetaQuotes").join("Terminal"));
+    }
+
+    ScanRoot {
+        program_dirs,
+        data_roots,
+        wine_prefix: Some(prefix.to_path_buf()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn native_root() -> Option<ScanRoot> {
+    let program_dirs = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(PathBuf::from)
+        .collect();
+    let data_roots = std::env::var("APPDATA")
+        .map(|appdata| vec![PathBuf::from(appdata).join("MetaQuotes").join("Terminal")])
+        .unwrap_or_default();
+    Some(ScanRoot {
+        program_dirs,
+        data_roots,
+        wine_prefix: None,
+    })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn native_root() -> Option<ScanRoot> {
+    None
+}
+
+fn find_installations(root: &ScanRoot) -> Vec<Installation> {
+    let mut installs = Vec::new();
+    for program_dir in &root.program_dirs {
+        for dir in list_dirs(program_dir) {
+            if let Some(install) = installation_at(&dir) {
+                installs.push(install);
+            }
+        }
+    }
+    installs
+}
+
+fn installation_at(dir: &Path) -> Option<Installation> {
+    let mt5_exe = dir.join("terminal64.exe");
+    let mt4_exe = dir.join("terminal.exe");
+    if mt5_exe.is_file() {
+        Some(Installation { kind: TerminalKind::Mt5, path: dir.to_path_buf(), executable: mt5_exe })
+    } else if mt4_exe.is_file() {
+        // Old 32-bit MT5 builds also ship terminal.exe; tell them apart by their MQL5/metaeditor64 files
+        let kind = if dir.join("MQL5").is_dir() || dir.join("metaeditor64.exe").is_file() {
+            TerminalKind::Mt5
+        } else {
+            TerminalKind::Mt4
+        };
+        Some(Installation { kind, path: dir.to_path_buf(), executable: mt4_exe })
+    } else {
+        None
+    }
+}
+
+fn data_kind(data_path: &Path) -> Option<TerminalKind> {
+    if data_path.join("MQL5").is_dir() {
+        Some(TerminalKind::Mt5)
+    } else if data_path.join("MQL4").is_dir() {
+        Some(TerminalKind::Mt4)
+    } else {
+        None
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+fn scan_root(root: &ScanRoot, found: &mut BTreeMap<PathBuf, DetectedTerminal>) {
+    let installs = find_installations(root);
+
+    // Portable installs keep their data next to the executable
+    for install in &installs {
+        if data_kind(&install.path).is_some() {
+            let mut terminal = DetectedTerminal::new(install.kind, install.path.clone(), true, root.wine_prefix.clone());
+            terminal.install_path = Some(install.path.clone());
+            terminal.executable = Some(install.executable.clone());
+            found.insert(install.path.clone(), terminal);
+        }
+    }
+
+    // AppData/Roaming/MetaQuotes/Terminal/<hash> folders point back to their install via origin.txt
+    for data_root in &root.data_roots {
+        for data_path in list_dirs(data_root) {
+            let Some(kind) = data_kind(&data_path) else {
+                continue;
+            };
+            let mut terminal = DetectedTerminal::new(kind, data_path.clone(), false, root.wine_prefix.clone());
+
+            if let Ok(bytes) = std::fs::read(data_path.join("origin.txt")) {
+                let install_path = host_path(&decode_text(&bytes), root.wine_prefix.as_deref());
+                terminal.name = folder_name(&install_path);
+                terminal.executable = installs
+                    .iter()
+                    .find(|i| same_path(&i.path, &install_path))
+                    .map(|i| i.executable.clone())
+                    .or_else(|| installation_at(&install_path).map(|i| i.executable));
+                terminal.install_path = Some(install_path);
+            }
+
+            found.insert(data_path, terminal);
+        }
+    }
+}
+
+/// Scans the native install locations (Windows), `~/.wine` and any extra Wine prefixes
+pub fn detect(extra_prefixes: &[PathBuf]) -> Vec<DetectedTerminal> {
+    let mut roots: Vec<ScanRoot> = native_root().into_iter().collect();
+
+    let mut prefixes: Vec<PathBuf> = extra_prefixes.to_vec();
+    if let Ok(home) = std::env::var("HOME") {
+        prefixes.push(PathBuf::from(home).join(".wine"));
+    }
+    prefixes.sort();
+    prefixes.dedup();
+    roots.extend(prefixes.iter().filter(|p| p.join("drive_c").is_dir()).map(|p| scan_wine_prefix(p)));
+
+    let mut found = BTreeMap::new();
+    for root in &roots {
+        scan_root(root, &mut found);
+    }
+    found.into_values().collect()
+}
+
+// ============ Commands ============
+
+/// Detects installed terminals. Wine prefixes of configured terminals are scanned too.
+/// The scan walks whole install trees, so it runs off the main thread.
+#[tauri::command]
+pub async fn detect_terminals(
+    wine_prefixes: Option<Vec<PathBuf>>,
+    store: tauri::State<'_, TerminalStore>,
+) -> Result<Vec<DetectedTerminal>, String> {
+    let mut prefixes = wine_prefixes.unwrap_or_default();
+    prefixes.extend(store.list().into_iter().filter_map(|t| t.wine_prefix));
+    tauri::async_runtime::spawn_blocking(move || detect(&prefixes))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16_with_bom(text: &str) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text(&utf16_with_bom("C:\\Program Files\\MT4")), "C:\\Program Files\\MT4");
+        assert_eq!(decode_text(b"plain"), "plain");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_host_path() {
+        let prefix = Path::new("/home/trader/.wine");
+        assert_eq!(
+            host_path("C:\\Program Files\\MetaTrader 4\r\n", Some(prefix)),
+            PathBuf::from("/home/trader/.wine/drive_c/Program Files/MetaTrader 4")
+        );
+        assert_eq!(host_path("Z:\\opt\\mt5", Some(prefix)), PathBuf::from("/opt/mt5"));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_detect_wine_prefix() {
+        let prefix = std::env::temp_dir().join("daavfx_discovery_test_prefix");
+        let _ = std::fs::remove_dir_all(&prefix);
+
+        let install = prefix.join("drive_c").join("Program Files").join("Broker MT4");
+        std::fs::create_dir_all(&install).unwrap();
+        std::fs::write(install.join("terminal.exe"), b"").unwrap();
+
+        let portable = prefix.join("drive_c").join("Program Files (x86)").join("Portable MT5");
+        std::fs::create_dir_all(portable.join("MQL5")).unwrap();
+        std::fs::write(portable.join("terminal64.exe"), b"").unwrap();
+
+        let data = prefix
+            .join("drive_c/users/trader/AppData/Roaming/MetaQuotes/Terminal")
+            .join("0123456789ABCDEF");
+        std::fs::create_dir_all(data.join("MQL4").join("Files")).unwrap();
+        std::fs::write(data.join("origin.txt"), utf16_with_bom("C:\\Program Files\\Broker MT4")).unwrap();
+
+        let found = detect(std::slice::from_ref(&prefix));
+        let mt4 = found.iter().find(|t| t.data_path == data).expect("MT4 data folder");
+        assert_eq!(mt4.kind, TerminalKind::Mt4);
+        assert_eq!(mt4.name, "Broker MT4");
+        assert_eq!(mt4.executable, Some(install.join("terminal.exe")));
+        assert_eq!(mt4.files_path, data.join("MQL4").join("Files"));
+        assert!(!mt4.portable);
+
+        let mt5 = found.iter().find(|t| t.data_path == portable).expect("portable MT5");
+        assert_eq!(mt5.kind, TerminalKind::Mt5);
+        assert!(mt5.portable);
+
+        let _ = std::fs::remove_dir_all(&prefix);
+    }
+}
@@ -0,0 +1,294 @@
+//! MT4/MT5 terminal log tailing
+//! Follows the journal (`logs/`) and experts log (`MQL4/Logs/`) of every configured terminal,
+//! decoding MetaTrader's UTF-16LE files and forwarding each line as an `app-log` event
+
+use crate::terminals::{TerminalKind, TerminalStore};
+use crate::app_control::record_log;
+use crate::LogPayload;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Follows the newest daily log in a directory. MetaTrader starts a new `YYYYMMDD.log` every day.
+pub struct LogTail {
+    dir: PathBuf,
+    current: Option<PathBuf>,
+    offset: u64,
+    utf16: bool,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl LogTail {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            current: None,
+            offset: 0,
+            utf16: false,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    fn newest_log(&self) -> Option<PathBuf> {
+        std::fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_daily_log(p))
+            .max()
+    }
+
+    /// Returns the complete lines appended since the last poll.
+    /// The first poll only positions at the end of today's file so old history is not replayed.
+    pub fn poll(&mut self) -> Vec<String> {
+        let first_poll = !self.started;
+        self.started = true;
+
+        let Some(newest) = self.newest_log() else {
+            return Vec::new();
+        };
+
+        let mut lines = Vec::new();
+        if self.current.as_ref() != Some(&newest) {
+            // Day rolled over: finish the old file first, including a last line without a newline
+            if let Some(old) = self.current.take() {
+                lines = self.read_new(&old);
+                let rest = std::mem::take(&mut self.pending);
+                lines.extend(self.decode(&rest));
+            }
+            // Start the new file (or the first one) from the top
+            self.current = Some(newest.clone());
+            self.offset = 0;
+            self.pending.clear();
+            if first_poll {
+                self.utf16 = detect_utf16(&newest);
+                self.offset = std::fs::metadata(&newest).map(|m| m.len()).unwrap_or(0);
+                return Vec::new();
+            }
+        }
+
+        lines.extend(self.read_new(&newest));
+        lines
+    }
+
+    /// Reads what was appended to `path` since `offset` and returns the completed lines
+    fn read_new(&mut self, path: &Path) -> Vec<String> {
+        let Ok(mut file) = std::fs::File::open(path) else {
+            return Vec::new();
+        };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < self.offset {
+            // Truncated or replaced - start over
+            self.offset = 0;
+            self.pending.clear();
+        }
+        // At the top of a file, wait until the BOM (if any) is there before picking the encoding
+        let min_len = if self.offset == 0 { 2 } else { self.offset + 1 };
+        if len < min_len || file.seek(SeekFrom::Start(self.offset)).is_err() {
+            return Vec::new();
+        }
+
+        let mut bytes = Vec::new();
+        if file.read_to_end(&mut bytes).is_err() {
+            return Vec::new();
+        }
+        if self.offset == 0 {
+            self.utf16 = bytes.starts_with(&[0xFF, 0xFE]);
+        }
+        self.offset += bytes.len() as u64;
+        self.pending.extend_from_slice(&bytes);
+        self.drain_lines()
+    }
+
+    /// Decodes every complete line in `pending`, keeping a trailing partial line for the next poll
+    fn drain_lines(&mut self) -> Vec<String> {
+        let end = if self.utf16 {
+            self.pending
+                .chunks_exact(2)
+                .rposition(|c| *c == [b'\n', 0])
+                .map(|i| (i + 1) * 2)
+        } else {
+            self.pending.iter().rposition(|b| *b == b'\n').map(|i| i + 1)
+        };
+        let Some(end) = end else {
+            return Vec::new();
+        };
+
+        let complete: Vec<u8> = self.pending.drain(..end).collect();
+        self.decode(&complete)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Vec<String> {
+        let text = if self.utf16 {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            String::from_utf8_lossy(bytes).to_string()
+        };
+
+        text.trim_start_matches('\u{feff}')
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// MetaTrader's daily logs are named `YYYYMMDD.log`; other logs in the folder (e.g. `metaeditor.log`) are ignored
+fn is_daily_log(path: &Path) -> bool {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("log"))
+        && stem.len() == 8
+        && stem.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn detect_utf16(path: &Path) -> bool {
+    let mut bom = [0u8; 2];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut bom))
+        .map(|_| bom == [0xFF, 0xFE])
+        .unwrap_or(false)
+}
+
+/// Maps MetaTrader's leading severity column (0 = info, 1 = warning, 2+ = error) to a log type
+fn log_type(line: &str) -> &'static str {
+    match line.split('\t').next().map(str::trim) {
+        Some("1") => "warn",
+        Some("2") | Some("3") => "error",
+        _ => "info",
+    }
+}
+
+struct TerminalTails {
+    journal: LogTail,
+    experts: LogTail,
+}
+
+/// Tails the logs of every configured terminal once per second until the app exits
+pub async fn run_log_tailer<R: Runtime>(app: AppHandle<R>) {
+    let store = app.state::<TerminalStore>();
+    let mut tails: HashMap<String, TerminalTails> = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_millis(1000));
+
+    loop {
+        interval.tick().await;
+
+        // Pick up terminals added or removed in Settings
+        let terminals = store.list();
+        tails.retain(|app_id, _| terminals.iter().any(|t| &t.app_id() == app_id));
+
+        for terminal in terminals {
+            let Some(data_dir) = terminal.data_dir() else {
+                continue;
+            };
+            let mql = match terminal.kind {
+                TerminalKind::Mt4 => "MQL4",
+                TerminalKind::Mt5 => "MQL5",
+            };
+            let app_id = terminal.app_id();
+            let tail = tails.entry(app_id.clone()).or_insert_with(|| TerminalTails {
+                journal: LogTail::new(data_dir.join("logs")),
+                experts: LogTail::new(data_dir.join(mql).join("Logs")),
+            });
+
+            for (source, lines) in [("journal", tail.journal.poll()), ("experts", tail.experts.poll())] {
+                for line in lines {
+                    record_log(&app, LogPayload {
+                        app_id: app_id.clone(),
+                        log_type: log_type(&line).to_string(),
+                        message: format!("[{}] {}", source, line),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// ============ Tests ============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    fn append(path: &Path, bytes: &[u8]) {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn test_tail_utf16_with_rollover() {
+        let dir = std::env::temp_dir().join("daavfx_terminal_logs_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let day1 = dir.join("20260101.log");
+        let mut content = vec![0xFF, 0xFE];
+        content.extend(utf16("0\t09:00:00.000\tTerminal\told history\r\n"));
+        std::fs::write(&day1, content).unwrap();
+
+        let mut tail = LogTail::new(dir.clone());
+        assert!(tail.poll().is_empty(), "existing history is skipped");
+
+        // A line split across two writes, including an odd byte boundary
+        let line = utf16("2\t09:00:01.000\tExpert Ryiuk: OrderSend error 134\r\n");
+        append(&day1, &line[..7]);
+        assert!(tail.poll().is_empty());
+        append(&day1, &line[7..]);
+        let lines = tail.poll();
+        assert_eq!(lines, vec!["2\t09:00:01.000\tExpert Ryiuk: OrderSend error 134"]);
+        assert_eq!(log_type(&lines[0]), "error");
+
+        // The last line of the day is written without a newline right before the new file appears
+        append(&day1, &utf16("0\t23:59:59.000\tTerminal\tlast line"));
+        let day2 = dir.join("20260102.log");
+        let mut content = vec![0xFF, 0xFE];
+        content.extend(utf16("0\t00:00:01.000\tTerminal\tnew day\r\n"));
+        std::fs::write(&day2, content).unwrap();
+        assert_eq!(
+            tail.poll(),
+            vec!["0\t23:59:59.000\tTerminal\tlast line", "0\t00:00:01.000\tTerminal\tnew day"]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rollover_to_empty_file() {
+        let dir = std::env::temp_dir().join("daavfx_terminal_logs_empty_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut tail = LogTail::new(dir.clone());
+        append(&dir.join("20260101.log"), b"old history\n");
+        append(&dir.join("metaeditor.log"), b"not the journal\n");
+        assert!(tail.poll().is_empty());
+
+        // The poll catches the new day's file before MetaTrader has written the BOM
+        let day2 = dir.join("20260102.log");
+        std::fs::write(&day2, b"").unwrap();
+        assert!(tail.poll().is_empty());
+        append(&day2, &[0xFF]);
+        assert!(tail.poll().is_empty());
+        append(&day2, &[0xFE]);
+        append(&day2, &utf16("0\t00:00:01.000\tTerminal\tnew day\r\n"));
+        assert_eq!(tail.poll(), vec!["0\t00:00:01.000\tTerminal\tnew day"]);
+        append(&day2, &utf16("1\t00:00:02.000\tTerminal\tsecond\r\n"));
+        assert_eq!(tail.poll(), vec!["1\t00:00:02.000\tTerminal\tsecond"]);
+        append(&dir.join("metaeditor.log"), b"still not the journal\n");
+        assert!(tail.poll().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
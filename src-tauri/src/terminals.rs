@@ -0,0 +1,313 @@
+//! MT4/MT5 terminal installs
+//! Configured terminals and the command lines used to launch them (natively or under Wine)
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalKind {
+    Mt4,
+    Mt5,
+}
+
+impl TerminalKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TerminalKind::Mt4 => "mt4",
+            TerminalKind::Mt5 => "mt5",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TerminalKind::Mt4 => "MT4",
+            TerminalKind::Mt5 => "MT5",
+        }
+    }
+}
+
+/// A terminal the launcher can start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalInstall {
+    pub id: String,
+    pub kind: TerminalKind,
+    /// Path to `terminal.exe` / `terminal64.exe`
+    pub executable: PathBuf,
+    /// Start with `/portable` so the data folder lives next to the executable
+    #[serde(default = "default_true")]
+    pub portable: bool,
+    /// Chart profile to open (`/profile:<name>`)
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Startup config `.ini` (login, server, EA to attach)
+    #[serde(default)]
+    pub config_file: Option<PathBuf>,
+    /// Data folder (`MQL4`/`MQL5`, `logs`); defaults to the install folder in portable mode
+    #[serde(default)]
+    pub data_path: Option<PathBuf>,
+    /// Wine prefix used on Linux/macOS (defaults to `~/.wine`)
+    #[serde(default)]
+    pub wine_prefix: Option<PathBuf>,
+    /// Wine binary used on Linux/macOS (defaults to `wine`)
+    #[serde(default)]
+    pub wine_binary: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl TerminalInstall {
+    /// Id used for this terminal in the `ProcessRegistry` and `app-log` events
+    pub fn app_id(&self) -> String {
+        format!("{}:{}", self.kind.as_str(), self.id)
+    }
+
+    /// Resolved data folder, if known
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        match &self.data_path {
+            Some(path) => Some(path.clone()),
+            None if self.portable => self.executable.parent().map(Path::to_path_buf),
+            None => None,
+        }
+    }
+
+    fn wine_prefix(&self) -> PathBuf {
+        self.wine_prefix.clone().unwrap_or_else(|| {
+            PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".wine")
+        })
+    }
+
+    /// Terminal arguments. Paths are translated to Windows paths when running under Wine.
+    fn launch_args(&self, under_wine: bool) -> Vec<String> {
+        let host_path = |p: &Path| {
+            if under_wine {
+                to_wine_path(p, &self.wine_prefix())
+            } else {
+                p.to_string_lossy().to_string()
+            }
+        };
+
+        let mut args = Vec::new();
+        if self.portable {
+            args.push("/portable".to_string());
+        }
+        if let Some(profile) = &self.profile {
+            args.push(format!("/profile:{}", profile));
+        }
+        if let Some(config) = &self.config_file {
+            match self.kind {
+                // MT4 takes the startup ini as a bare argument
+                TerminalKind::Mt4 => args.push(host_path(config)),
+                TerminalKind::Mt5 => args.push(format!("/config:{}", host_path(config))),
+            }
+        }
+        args
+    }
+
+    /// Builds the command that starts this terminal
+    pub fn build_command(&self) -> Result<Command, String> {
+        if !self.executable.exists() {
+            return Err(format!(
+                "{} terminal '{}' not found at {:?}",
+                self.kind.label(),
+                self.id,
+                self.executable
+            ));
+        }
+        let work_dir = self.executable.parent().ok_or("Terminal executable has no parent directory")?;
+
+        #[cfg(target_os = "windows")]
+        let mut command = {
+            let mut command = Command::new(&self.executable);
+            command.args(self.launch_args(false));
+            command
+        };
+
+        #[cfg(not(target_os = "windows"))]
+        let mut command = {
+            let prefix = self.wine_prefix();
+            if !prefix.exists() {
+                return Err(format!("Wine prefix not found: {:?}", prefix));
+            }
+            let mut command = Command::new(self.wine_binary.as_deref().unwrap_or("wine"));
+            command
+                .arg(&self.executable)
+                .args(self.launch_args(true))
+                .env("WINEPREFIX", &prefix);
+            if std::env::var("WINEDEBUG").is_err() {
+                command.env("WINEDEBUG", "-all");
+            }
+            command
+        };
+
+        command.current_dir(work_dir);
+        Ok(command)
+    }
+}
+
+/// Maps a host path to the path Wine sees: `C:\...` inside the prefix's drive_c, `Z:\...` otherwise
+pub fn to_wine_path(path: &Path, prefix: &Path) -> String {
+    let (drive, rest) = match path.strip_prefix(prefix.join("drive_c")) {
+        Ok(rest) => ("C:", rest),
+        Err(_) => ("Z:", path.strip_prefix("/").unwrap_or(path)),
+    };
+    let rest: Vec<String> = rest.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    format!("{}\\{}", drive, rest.join("\\"))
+}
+
+/// Configured terminals, stored as `terminals.json` in the app data dir
+pub struct TerminalStore {
+    path: PathBuf,
+    terminals: Mutex<Vec<TerminalInstall>>,
+}
+
+impl TerminalStore {
+    pub fn load(path: PathBuf) -> Self {
+        let terminals = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            terminals: Mutex::new(terminals),
+        }
+    }
+
+    fn save(&self, terminals: &[TerminalInstall]) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let json = serde_json::to_string_pretty(terminals).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json).map_err(|e| format!("Failed to write {:?}: {}", self.path, e))
+    }
+
+    pub fn list(&self) -> Vec<TerminalInstall> {
+        self.terminals.lock().unwrap().clone()
+    }
+
+    /// Finds a terminal of `kind`, by id if given, otherwise the first one configured
+    pub fn find(&self, kind: TerminalKind, id: Option<&str>) -> Result<TerminalInstall, String> {
+        let terminals = self.terminals.lock().unwrap();
+        let mut candidates = terminals.iter().filter(|t| t.kind == kind);
+        match id {
+            Some(id) => candidates
+                .find(|t| t.id == id)
+                .cloned()
+                .ok_or_else(|| format!("No {} terminal configured with id '{}'", kind.label(), id)),
+            None => candidates.next().cloned().ok_or_else(|| {
+                format!("No {} terminal configured. Add one in Settings before launching.", kind.label())
+            }),
+        }
+    }
+
+    /// Inserts or replaces the terminal with the same kind and id
+    pub fn upsert(&self, terminal: TerminalInstall) -> Result<(), String> {
+        if terminal.id.trim().is_empty() {
+            return Err("Terminal id must not be empty".to_string());
+        }
+        let mut terminals = self.terminals.lock().unwrap();
+        match terminals.iter_mut().find(|t| t.kind == terminal.kind && t.id == terminal.id) {
+            Some(existing) => *existing = terminal,
+            None => terminals.push(terminal),
+        }
+        self.save(&terminals)
+    }
+
+    pub fn remove(&self, kind: TerminalKind, id: &str) -> Result<(), String> {
+        let mut terminals = self.terminals.lock().unwrap();
+        let before = terminals.len();
+        terminals.retain(|t| !(t.kind == kind && t.id == id));
+        if terminals.len() == before {
+            return Err(format!("No {} terminal configured with id '{}'", kind.label(), id));
+        }
+        self.save(&terminals)
+    }
+}
+
+// ============ Commands ============
+
+#[tauri::command]
+pub fn list_terminals(store: tauri::State<'_, TerminalStore>) -> Vec<TerminalInstall> {
+    store.list()
+}
+
+#[tauri::command]
+pub fn save_terminal(terminal: TerminalInstall, store: tauri::State<'_, TerminalStore>) -> Result<(), String> {
+    store.upsert(terminal)
+}
+
+#[tauri::command]
+pub fn remove_terminal(
+    kind: TerminalKind,
+    terminal_id: String,
+    store: tauri::State<'_, TerminalStore>,
+) -> Result<(), String> {
+    store.remove(kind, &terminal_id)
+}
+
+// ============ Tests ============
+
+// Wine path handling only applies off Windows
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+
+    fn install(kind: TerminalKind) -> TerminalInstall {
+        TerminalInstall {
+            id: "ic".into(),
+            kind,
+            executable: PathBuf::from("/home/trader/.wine/drive_c/Program Files/MT/terminal.exe"),
+            portable: true,
+            profile: Some("Scalping".into()),
+            config_file: Some(PathBuf::from("/home/trader/configs/start.ini")),
+            data_path: None,
+            wine_prefix: Some(PathBuf::from("/home/trader/.wine")),
+            wine_binary: None,
+        }
+    }
+
+    #[test]
+    fn test_wine_path_mapping() {
+        let prefix = Path::new("/home/trader/.wine");
+        assert_eq!(
+            to_wine_path(Path::new("/home/trader/.wine/drive_c/Program Files/MT4/terminal.exe"), prefix),
+            "C:\\Program Files\\MT4\\terminal.exe"
+        );
+        assert_eq!(to_wine_path(Path::new("/opt/configs/a.ini"), prefix), "Z:\\opt\\configs\\a.ini");
+    }
+
+    #[test]
+    fn test_launch_args() {
+        assert_eq!(
+            install(TerminalKind::Mt5).launch_args(true),
+            vec!["/portable", "/profile:Scalping", "/config:Z:\\home\\trader\\configs\\start.ini"]
+        );
+        assert_eq!(
+            install(TerminalKind::Mt4).launch_args(true),
+            vec!["/portable", "/profile:Scalping", "Z:\\home\\trader\\configs\\start.ini"]
+        );
+        assert_eq!(install(TerminalKind::Mt4).app_id(), "mt4:ic");
+    }
+
+    #[test]
+    fn test_store_keys_on_kind_and_id() {
+        let path = std::env::temp_dir().join("daavfx_terminals_test.json");
+        let _ = std::fs::remove_file(&path);
+        let store = TerminalStore::load(path.clone());
+
+        store.upsert(install(TerminalKind::Mt4)).unwrap();
+        store.upsert(install(TerminalKind::Mt5)).unwrap();
+        assert_eq!(store.list().len(), 2, "same id of another kind is a separate terminal");
+
+        store.remove(TerminalKind::Mt5, "ic").unwrap();
+        assert!(store.find(TerminalKind::Mt4, Some("ic")).is_ok());
+        assert!(store.find(TerminalKind::Mt5, Some("ic")).is_err());
+        assert!(store.remove(TerminalKind::Mt5, "ic").is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}